@@ -1,19 +1,107 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{DeriveInput, parse_macro_input, parse_quote};
+use syn::{Data, DeriveInput, Index, LitStr, parse_macro_input, parse_quote};
 
-#[proc_macro_derive(Component)]
+#[proc_macro_derive(Component, attributes(component))]
 pub fn component_derive(input: TokenStream) -> TokenStream {
     let mut ast = parse_macro_input!(input as DeriveInput);
 
+    let mut pod = false;
+    let mut stable_name = None;
+    let mut storage = quote! { ssecs::component::Storage::Table };
+    for attr in ast.attrs.iter().filter(|attr| attr.path().is_ident("component")) {
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("pod") {
+                pod = true;
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                stable_name = Some(meta.value()?.parse::<LitStr>()?);
+                Ok(())
+            } else if meta.path.is_ident("storage") {
+                let kind = meta.value()?.parse::<LitStr>()?;
+                storage = match kind.value().as_str() {
+                    "table" => quote! { ssecs::component::Storage::Table },
+                    "sparse" => quote! { ssecs::component::Storage::Sparse },
+                    _ => return Err(meta.error("expected `table` or `sparse`")),
+                };
+                Ok(())
+            } else {
+                Err(meta.error("unsupported component attribute"))
+            }
+        });
+        if let Err(err) = parsed {
+            return err.to_compile_error().into();
+        }
+    }
+
     ast.generics
         .make_where_clause()
         .predicates
         .push(parse_quote! { Self: Sized + Send + Sync + 'static });
+    if pod {
+        ast.generics.make_where_clause().predicates.push(parse_quote! { Self: Copy });
+    }
 
     let struct_name = &ast.ident;
     let (impl_generics, type_generics, where_clause) = &ast.generics.split_for_impl();
 
+    // Pod components are copied as bytes so they can't contain padding
+    let padding_check = match &ast.data {
+        Data::Struct(data) if pod => {
+            let sizes = data.fields.iter().map(|field| {
+                let ty = &field.ty;
+                quote! { + std::mem::size_of::<#ty>() }
+            });
+            quote! {
+                const {
+                    assert!(
+                        std::mem::size_of::<Self>() == 0 #(#sizes)*,
+                        "pod components can't contain padding",
+                    )
+                };
+            }
+        }
+        Data::Enum(_) | Data::Union(_) if pod => {
+            return syn::Error::new_spanned(&ast.ident, "only structs can be pod components")
+                .to_compile_error()
+                .into();
+        }
+        _ => quote! {},
+    };
+
+    let stable_id = match stable_name {
+        Some(name) => quote! { ssecs::component::stable_id(#name) },
+        None => quote! { ssecs::component::stable_id(std::any::type_name::<#struct_name>()) },
+    };
+
+    let members = match &ast.data {
+        Data::Struct(data) => data
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(n, field)| {
+                let ty = &field.ty;
+                let (name, member) = match &field.ident {
+                    Some(ident) => (ident.to_string(), quote! { #ident }),
+                    None => {
+                        let index = Index::from(n);
+                        (n.to_string(), quote! { #index })
+                    }
+                };
+                quote! {
+                    ssecs::component::MemberInfo {
+                        name: #name,
+                        offset: std::mem::offset_of!(Self, #member),
+                        type_name: std::any::type_name::<#ty>,
+                        type_id: std::any::TypeId::of::<#ty>,
+                        component: Getter::<#ty>::component as fn() -> _,
+                    }
+                }
+            })
+            .collect(),
+        Data::Enum(_) | Data::Union(_) => Vec::new(),
+    };
+
     let output = quote! {
         unsafe impl #impl_generics ssecs::component::Component for #struct_name #type_generics
         #where_clause
@@ -35,20 +123,60 @@ pub fn component_derive(input: TokenStream) -> TokenStream {
             }
 
             fn info() -> ssecs::component::ComponentInfo {
+                use ssecs::component::detect::*;
+                #padding_check
                 unsafe {
                     ssecs::component::ComponentInfo {
                         name: std::any::type_name::<#struct_name>(),
                         align: std::mem::align_of::<#struct_name>(),
                         size: std::mem::size_of::<#struct_name>(),
                         id: #struct_name::id(),
+                        stable_id: #stable_id,
+                        storage: #storage,
+                        pod: #pod,
+                        members: const { &[#(#members),*] },
                         clone: #struct_name::get_erased_clone(),
                         default: #struct_name::get_erased_default(),
                         drop: #struct_name::erased_drop,
                         on_insert: #struct_name::get_on_insert(),
                         on_remove: #struct_name::get_on_remove(),
+                        serde: #struct_name::get_serde(),
+                        debug: #struct_name::get_debug(),
                     }
                 }
             }
+
+            fn get_erased_clone() -> Option<
+                unsafe fn(&[std::mem::MaybeUninit<u8>]) -> Box<[std::mem::MaybeUninit<u8>]>,
+            > {
+                use ssecs::component::detect::*;
+                Getter::<Self>::erased_clone()
+            }
+
+            fn get_erased_default() -> Option<fn() -> Box<[std::mem::MaybeUninit<u8>]>> {
+                use ssecs::component::detect::*;
+                Getter::<Self>::erased_default()
+            }
+
+            fn get_on_insert() -> Option<fn(ssecs::entity::View<'_>)> {
+                use ssecs::component::detect::*;
+                Getter::<Self>::on_insert()
+            }
+
+            fn get_on_remove() -> Option<fn(ssecs::entity::View<'_>)> {
+                use ssecs::component::detect::*;
+                Getter::<Self>::on_remove()
+            }
+
+            fn get_serde() -> Option<ssecs::component::SerdeInfo> {
+                use ssecs::component::detect::*;
+                Getter::<Self>::serde()
+            }
+
+            fn get_debug() -> Option<ssecs::component::DebugFn> {
+                use ssecs::component::detect::*;
+                Getter::<Self>::debug()
+            }
         }
     };
 
@@ -1,8 +1,15 @@
 use std::{
+    any::TypeId,
+    borrow::Cow,
+    collections::BTreeSet,
+    fmt,
     marker::PhantomData,
     mem::{ManuallyDrop, MaybeUninit},
 };
 
+use aligned_vec::{AVec, RuntimeAlign};
+use parking_lot::Mutex;
+
 use crate::{self as ssecs, entity::Entity, entity::View, world::World};
 use ssecs_macros::*;
 
@@ -11,96 +18,210 @@ pub type ComponentEntry = fn(world: &World);
 #[linkme::distributed_slice]
 pub static COMPONENT_ENTRIES: [ComponentEntry];
 
+/// Hash a component path into an id that is the same across builds & processes.
+/// Uses 64 bit FNV-1a.
+pub const fn stable_id(path: &str) -> u64 {
+    let bytes = path.as_bytes();
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    let mut n = 0;
+    while n < bytes.len() {
+        hash ^= bytes[n] as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+        n += 1;
+    }
+    hash
+}
+
 /// # Safety
 /// Should never be implemented manually
 pub unsafe trait Component: Sized {
     fn id() -> Entity;
     fn init(_: &World);
     fn info() -> ComponentInfo;
+    fn get_erased_clone() -> Option<unsafe fn(&[MaybeUninit<u8>]) -> Box<[MaybeUninit<u8>]>>;
+    fn get_erased_default() -> Option<fn() -> Box<[MaybeUninit<u8>]>>;
+    fn get_on_insert() -> Option<fn(View<'_>)>;
+    fn get_on_remove() -> Option<fn(View<'_>)>;
+    fn get_serde() -> Option<SerdeInfo>;
+    fn get_debug() -> Option<DebugFn>;
 
-    fn get_erased_clone() -> Option<unsafe fn(&[MaybeUninit<u8>]) -> &'static [MaybeUninit<u8>]> {
-        struct Getter<T>(PhantomData<T>);
-        impl<T: Clone> Getter<T> {
-            #[allow(dead_code)]
-            fn get() -> Option<unsafe fn(&[MaybeUninit<u8>]) -> &'static [MaybeUninit<u8>]> {
-                Some(|bytes| unsafe {
-                    let t = (bytes.as_ptr() as *const T).as_ref().unwrap();
-                    let leaked = ManuallyDrop::new(t.clone());
-                    std::slice::from_raw_parts((&raw const leaked).cast(), size_of::<Self>())
-                })
-            }
+    #[allow(clippy::missing_safety_doc)]
+    unsafe fn erased_drop(bytes: &mut [std::mem::MaybeUninit<u8>]) {
+        // SAFETY: Caller guarantees bytes are an owned value of `Self`
+        unsafe { (bytes.as_ptr() as *mut Self).drop_in_place() }
+    }
+}
+
+/// Trait detection used by the derive macro.
+/// Only resolves to an impl when used with a concrete type, which is why it's called from the
+/// derived impl instead of from default methods of [`Component`] where `Self` is generic.
+#[doc(hidden)]
+pub mod detect {
+    use super::*;
+
+    pub struct Getter<T>(PhantomData<T>);
+
+    fn erase<T>(val: T) -> Box<[MaybeUninit<u8>]> {
+        let leaked = ManuallyDrop::new(val);
+        // SAFETY: Reads the `size_of::<T>()` bytes of a live local. Ownership moves to the copy.
+        unsafe { std::slice::from_raw_parts((&raw const leaked).cast(), size_of::<T>()) }.into()
+    }
+
+    impl<T: Clone> Getter<T> {
+        pub fn erased_clone() -> Option<unsafe fn(&[MaybeUninit<u8>]) -> Box<[MaybeUninit<u8>]>> {
+            Some(|bytes| {
+                // SAFETY: Caller guarantees bytes are an aligned value of `T`
+                erase(unsafe { (bytes.as_ptr() as *const T).as_ref() }.unwrap().clone())
+            })
         }
-        trait NoImpl<T> {
-            fn get() -> Option<unsafe fn(&[MaybeUninit<u8>]) -> &'static [MaybeUninit<u8>]> {
-                None
-            }
+    }
+
+    pub trait NoClone {
+        fn erased_clone() -> Option<unsafe fn(&[MaybeUninit<u8>]) -> Box<[MaybeUninit<u8>]>> {
+            None
         }
-        impl<T> NoImpl<T> for Getter<T> {}
-        Getter::<Self>::get()
-    }
-
-    fn get_erased_default() -> Option<fn() -> &'static [MaybeUninit<u8>]> {
-        struct Getter<T>(PhantomData<T>);
-        impl<T: Default> Getter<T> {
-            #[allow(dead_code)]
-            fn get() -> Option<fn() -> &'static [MaybeUninit<u8>]> {
-                Some(|| {
-                    let leaked = ManuallyDrop::new(T::default());
-                    unsafe {
-                        std::slice::from_raw_parts((&raw const leaked).cast(), size_of::<Self>())
-                    }
-                })
-            }
+    }
+
+    impl<T> NoClone for Getter<T> {}
+
+    impl<T: Default> Getter<T> {
+        pub fn erased_default() -> Option<fn() -> Box<[MaybeUninit<u8>]>> {
+            Some(|| erase(T::default()))
         }
-        trait NoImpl<T> {
-            fn get() -> Option<fn() -> &'static [MaybeUninit<u8>]> {
-                None
-            }
+    }
+
+    pub trait NoDefault {
+        fn erased_default() -> Option<fn() -> Box<[MaybeUninit<u8>]>> {
+            None
         }
-        impl<T> NoImpl<T> for Getter<T> {}
-        Getter::<Self>::get()
     }
 
-    #[allow(clippy::missing_safety_doc)]
-    unsafe fn erased_drop(bytes: &mut [std::mem::MaybeUninit<u8>]) {
-        unsafe { (bytes.as_ptr() as *mut Self).drop_in_place() }
+    impl<T> NoDefault for Getter<T> {}
+
+    impl<T: fmt::Debug> Getter<T> {
+        pub fn debug() -> Option<DebugFn> {
+            Some(|bytes, f| {
+                // SAFETY: Caller guarantees bytes are an aligned value of `T`
+                fmt::Debug::fmt(unsafe { (bytes.as_ptr() as *const T).as_ref() }.unwrap(), f)
+            })
+        }
     }
 
-    fn get_on_insert() -> Option<fn(View<'_>)> {
-        struct Getter<T>(PhantomData<T>);
-        impl<T: OnInsert> Getter<T> {
-            #[allow(dead_code)]
-            fn get() -> Option<fn(View<'_>)> {
-                Some(T::on_insert)
-            }
+    pub trait NoDebug {
+        fn debug() -> Option<DebugFn> {
+            None
         }
-        trait NoImpl<T> {
-            fn get() -> Option<fn(View<'_>)> {
-                None
-            }
+    }
+
+    impl<T> NoDebug for Getter<T> {}
+
+    impl<T: OnInsert> Getter<T> {
+        pub fn on_insert() -> Option<fn(View<'_>)> {
+            Some(T::on_insert)
         }
-        impl<T> NoImpl<T> for Getter<T> {}
-        Getter::<Self>::get()
     }
 
-    fn get_on_remove() -> Option<fn(View<'_>)> {
-        struct Getter<T>(PhantomData<T>);
-        impl<T: OnRemove> Getter<T> {
-            #[allow(dead_code)]
-            fn get() -> Option<fn(View<'_>)> {
-                Some(T::on_remove)
-            }
+    pub trait NoOnInsert {
+        fn on_insert() -> Option<fn(View<'_>)> {
+            None
         }
-        trait NoImpl<T> {
-            fn get() -> Option<fn(View<'_>)> {
-                None
-            }
+    }
+
+    impl<T> NoOnInsert for Getter<T> {}
+
+    impl<T: OnRemove> Getter<T> {
+        pub fn on_remove() -> Option<fn(View<'_>)> {
+            Some(T::on_remove)
+        }
+    }
+
+    pub trait NoOnRemove {
+        fn on_remove() -> Option<fn(View<'_>)> {
+            None
+        }
+    }
+
+    impl<T> NoOnRemove for Getter<T> {}
+
+    impl<T: Component> Getter<T> {
+        pub fn component() -> Option<Entity> {
+            Some(T::id())
+        }
+    }
+
+    pub trait NoComponent {
+        fn component() -> Option<Entity> {
+            None
         }
-        impl<T> NoImpl<T> for Getter<T> {}
-        Getter::<Self>::get()
     }
+
+    impl<T> NoComponent for Getter<T> {}
+
+    #[cfg(feature = "serde")]
+    impl<T: serde::Serialize + serde::de::DeserializeOwned + 'static> Getter<T> {
+        pub fn serde() -> Option<SerdeInfo> {
+            Some(SerdeInfo {
+                // SAFETY: Caller guarantees bytes are an aligned value of `T`
+                serialize: |bytes| unsafe { (bytes.as_ptr() as *const T).as_ref() }.unwrap(),
+                deserialize: |deserializer| erased_serde::deserialize::<T>(deserializer).map(erase),
+            })
+        }
+    }
+
+    pub trait NoSerde {
+        fn serde() -> Option<SerdeInfo> {
+            None
+        }
+    }
+
+    impl<T> NoSerde for Getter<T> {}
+}
+
+/// One or more components. Implemented for components & tuples of bundles.
+pub trait Bundle: Sized {
+    fn infos(infos: &mut Vec<ComponentInfo>);
+    /// Pass the bytes of each component to `func` in the same order as [`Bundle::infos`].
+    /// Values are owned by `func` after.
+    fn take(self, func: &mut impl FnMut(&[MaybeUninit<u8>]));
 }
 
+impl<T: Component> Bundle for T {
+    fn infos(infos: &mut Vec<ComponentInfo>) {
+        infos.push(T::info());
+    }
+
+    fn take(self, func: &mut impl FnMut(&[MaybeUninit<u8>])) {
+        let leaked = ManuallyDrop::new(self);
+        // SAFETY: Reads the `size_of::<T>()` bytes of a live local. Ownership moves to `func`.
+        func(unsafe { std::slice::from_raw_parts((&raw const leaked).cast(), size_of::<T>()) });
+    }
+}
+
+macro_rules! impl_bundle {
+    ($($name:ident),*) => {
+        impl<$($name: Bundle),*> Bundle for ($($name,)*) {
+            fn infos(infos: &mut Vec<ComponentInfo>) {
+                $($name::infos(infos);)*
+            }
+
+            #[allow(non_snake_case)]
+            fn take(self, func: &mut impl FnMut(&[MaybeUninit<u8>])) {
+                let ($($name,)*) = self;
+                $($name.take(func);)*
+            }
+        }
+    };
+}
+
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+
 pub trait OnInsert {
     fn on_insert(entity: View<'_>);
 }
@@ -115,13 +236,127 @@ pub struct ComponentInfo {
     pub align: usize,
     pub size: usize,
     pub id: Entity,
-    pub clone: Option<unsafe fn(&[MaybeUninit<u8>]) -> &'static [MaybeUninit<u8>]>,
-    pub default: Option<fn() -> &'static [MaybeUninit<u8>]>,
+    /// Hash of `#[component(name = "...")]` or the type name. See [`stable_id`].
+    pub stable_id: u64,
+    /// Set with `#[component(storage = "sparse")]`
+    pub storage: Storage,
+    /// Set with `#[component(pod)]`. Values can be copied as bytes & have no drop.
+    pub pod: bool,
+    pub members: &'static [MemberInfo],
+    /// Clone of an aligned value. The returned buffer owns the clone but may not be aligned,
+    /// so read it with `read_unaligned` or drop it with [`ComponentInfo::drop_unaligned`].
+    pub clone: Option<unsafe fn(&[MaybeUninit<u8>]) -> Box<[MaybeUninit<u8>]>>,
+    /// Default value. Owned & aligned like the buffer returned by `clone`.
+    pub default: Option<fn() -> Box<[MaybeUninit<u8>]>>,
     pub drop: unsafe fn(&mut [MaybeUninit<u8>]),
     pub on_insert: Option<fn(View<'_>)>,
     pub on_remove: Option<fn(View<'_>)>,
+    pub serde: Option<SerdeInfo>,
+    pub debug: Option<DebugFn>,
+}
+
+impl ComponentInfo {
+    /// Describe a component that has no Rust type. Used with [`World::register_component`].
+    /// The `id` is assigned on registration, the stable id is derived from `name`
+    /// & the value is dropped as plain bytes.
+    /// Owned names are kept for the life of the process & shared by infos with the same name.
+    ///
+    /// Will panic if `align` is not a power of two or `size` is not a multiple of `align`.
+    pub fn new(name: impl Into<Cow<'static, str>>, size: usize, align: usize) -> Self {
+        assert!(align.is_power_of_two(), "Alignment must be a power of two");
+        assert_eq!(size % align, 0, "Size must be a multiple of alignment");
+        let name = intern(name.into());
+        Self {
+            name,
+            align,
+            size,
+            id: Entity::null(),
+            stable_id: stable_id(name),
+            storage: Storage::Table,
+            pod: false,
+            members: &[],
+            clone: None,
+            default: None,
+            drop: |_| {},
+            on_insert: None,
+            on_remove: None,
+            serde: None,
+            debug: None,
+        }
+    }
+
+    /// Drop a value held in a buffer that may not be aligned for the component
+    ///
+    /// # Safety
+    /// `bytes` must be an owned value of the component that is never used again
+    pub unsafe fn drop_unaligned(&self, bytes: &[MaybeUninit<u8>]) {
+        let mut aligned = AVec::<_, RuntimeAlign>::from_slice(self.align, bytes);
+        // SAFETY: Aligned copy of the value, which the caller gave up
+        unsafe { (self.drop)(&mut aligned) };
+    }
+}
+
+/// Names of runtime components that weren't `'static`
+static NAMES: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+
+fn intern(name: Cow<'static, str>) -> &'static str {
+    let name = match name {
+        Cow::Borrowed(name) => return name,
+        Cow::Owned(name) => name,
+    };
+    let mut names = NAMES.lock();
+    if let Some(interned) = names.get(name.as_str()) {
+        return interned;
+    }
+    let interned = Box::leak(name.into_boxed_str());
+    names.insert(interned);
+    interned
+}
+
+/// Formats a type erased component value with its `Debug` impl
+pub type DebugFn = unsafe fn(&[MaybeUninit<u8>], &mut fmt::Formatter<'_>) -> fmt::Result;
+
+/// Where values of a component are stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Storage {
+    /// In archetype columns. Fastest to iterate.
+    #[default]
+    Table,
+    /// In a sparse set keyed by entity. Not part of the archetype so inserting or removing
+    /// doesn't move the entity.
+    Sparse,
+}
+
+/// Layout of a field in a component's Rust type. Empty for enums & runtime components.
+#[derive(Clone, Copy, Debug)]
+pub struct MemberInfo {
+    /// Field name or tuple index
+    pub name: &'static str,
+    /// Offset in bytes from the start of the component
+    pub offset: usize,
+    pub type_name: fn() -> &'static str,
+    pub type_id: fn() -> TypeId,
+    /// Id of the field's type if it is also a component.
+    /// Detected where the component is derived, so a field whose type is a generic parameter
+    /// always gets `None` even if the parameter is a component.
+    pub component: fn() -> Option<Entity>,
+}
+
+/// Type erased serde impls of a component. Available with the `serde` feature.
+#[derive(Clone, Copy, Debug)]
+pub struct SerdeInfo {
+    #[cfg(feature = "serde")]
+    pub serialize: for<'a> unsafe fn(&'a [MaybeUninit<u8>]) -> &'a dyn erased_serde::Serialize,
+    #[cfg(feature = "serde")]
+    pub deserialize: fn(
+        &mut dyn erased_serde::Deserializer,
+    ) -> Result<Box<[MaybeUninit<u8>]>, erased_serde::Error>,
 }
 
+/// Hides an entity from queries that don't opt in. See [`View::disable`].
+#[derive(Component)]
+pub struct Disabled;
+
 pub mod traits {
     use crate::{self as ssecs, component::Component, entity::Entity};
 
@@ -148,6 +383,106 @@ pub(crate) mod tests {
     #[derive(Component)]
     pub struct Health;
 
+    #[derive(Component, Clone, Default)]
+    pub struct Velocity {
+        pub x: f32,
+        pub y: f32,
+    }
+
+    #[derive(Component)]
+    pub struct Body(pub u8, pub Velocity);
+
+    #[test]
+    fn members() {
+        let info = Body::info();
+        assert_eq!(
+            info.members.iter().map(|member| member.name).collect::<Vec<_>>(),
+            ["0", "1"]
+        );
+
+        let velocity = info.members[1];
+        assert_eq!(velocity.offset, std::mem::offset_of!(Body, 1));
+        assert_eq!((velocity.type_name)(), std::any::type_name::<Velocity>());
+        assert_eq!((velocity.type_id)(), TypeId::of::<Velocity>());
+        assert_eq!((velocity.component)(), Some(Velocity::id()));
+        assert_eq!((info.members[0].component)(), None);
+
+        let velocity = Velocity::info();
+        assert_eq!(
+            velocity.members.iter().map(|member| member.name).collect::<Vec<_>>(),
+            ["x", "y"]
+        );
+        assert!(Player::info().members.is_empty());
+    }
+
+    #[test]
+    fn detect_traits() {
+        assert!(Velocity::info().clone.is_some());
+        assert!(Velocity::info().default.is_some());
+        assert!(Body::info().clone.is_none());
+        assert!(Body::info().default.is_none());
+        assert!(Velocity::info().debug.is_none());
+        assert!(ComponentInfo::info().debug.is_some());
+
+        let info = Velocity::info();
+        let bytes = (info.default.unwrap())();
+        let aligned = AVec::<_, RuntimeAlign>::from_slice(info.align, &bytes);
+        let cloned = unsafe { (info.clone.unwrap())(&aligned) };
+        let velocity = unsafe { (cloned.as_ptr() as *const Velocity).read_unaligned() };
+        assert_eq!((velocity.x, velocity.y), (0.0, 0.0));
+        unsafe { info.drop_unaligned(&aligned) };
+    }
+
+    #[derive(Component, Clone)]
+    pub struct Label(String);
+
+    #[test]
+    fn owned_clone() {
+        let label = Label(String::from("label"));
+        let bytes =
+            unsafe { std::slice::from_raw_parts((&raw const label).cast(), size_of::<Label>()) };
+        let cloned = unsafe { (Label::info().clone.unwrap())(bytes) };
+        drop(label);
+        let moved = unsafe { (cloned.as_ptr() as *const Label).read_unaligned() };
+        assert_eq!("label", moved.0);
+
+        let bytes =
+            unsafe { std::slice::from_raw_parts((&raw const moved).cast(), size_of::<Label>()) };
+        let cloned = unsafe { (Label::info().clone.unwrap())(bytes) };
+        unsafe { Label::info().drop_unaligned(&cloned) };
+    }
+
+    #[test]
+    fn runtime_info() {
+        let info = ComponentInfo::new(format!("Runtime{}", 0), 8, 4);
+        assert_eq!("Runtime0", info.name);
+        assert!(std::ptr::eq(
+            info.name,
+            ComponentInfo::new(String::from("Runtime0"), 0, 1).name
+        ));
+        assert_eq!(stable_id("Runtime0"), info.stable_id);
+    }
+
+    #[test]
+    #[should_panic]
+    fn runtime_info_align() {
+        ComponentInfo::new("Misaligned", 6, 4);
+    }
+
+    #[derive(Component)]
+    #[component(name = "renamed::Health")]
+    pub struct Renamed;
+
+    #[test]
+    fn stable_ids() {
+        assert_eq!(Renamed::info().stable_id, stable_id("renamed::Health"));
+        assert_eq!(
+            Player::info().stable_id,
+            stable_id(std::any::type_name::<Player>())
+        );
+        assert_ne!(Player::info().stable_id, Transform::info().stable_id);
+    }
+
     #[test]
     fn component_ids() {
         assert!(Player::id() != Transform::id());
@@ -1,6 +1,7 @@
 use std::{
+    fmt,
+    mem::MaybeUninit,
     ops::{Deref, DerefMut},
-    sync::atomic::AtomicUsize,
 };
 
 use derive_more::From;
@@ -8,7 +9,7 @@ use parking_lot::{MappedRwLockReadGuard, MappedRwLockWriteGuard};
 
 use crate::slotmap::*;
 
-#[derive(Clone, Copy, Debug, From, PartialEq, Eq)]
+#[derive(Clone, Copy, From, PartialEq, Eq, Hash)]
 pub struct Entity(pub(crate) Key);
 
 impl From<Entity> for Key {
@@ -18,10 +19,15 @@ impl From<Entity> for Key {
 }
 
 use crate::{
-    NonZstOrPanic,
-    component::Component,
+    Error, NonZstOrPanic,
+    component::{Component, ComponentInfo, Disabled},
+    hierarchy::Name,
     query::AccessTuple,
-    world::{Crust, Mantle, World, archetype::FieldId, command::Command},
+    world::{
+        Crust, FlushGuard, Mantle, World,
+        archetype::{Column, FieldId},
+        command::Command,
+    },
 };
 
 impl Entity {
@@ -48,6 +54,22 @@ impl Entity {
     }
 }
 
+/// `index v generation`, e.g. `12v1`
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.is_null() {
+            true => write!(f, "null"),
+            false => write!(f, "{}v{}", self.0.index, self.0.generation),
+        }
+    }
+}
+
+impl fmt::Debug for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct View<'a> {
     pub(crate) entity: Entity,
@@ -66,6 +88,14 @@ impl View<'_> {
         self
     }
 
+    /// Add a relationship to `target` with `relationship` as its value
+    pub fn insert_pair<C: Component>(self, relationship: C, target: Entity) -> Self {
+        self.world.crust.mantle(|mantle| {
+            mantle.enqueue(Command::insert_pair(relationship, target, self.entity));
+        });
+        self
+    }
+
     pub fn remove<Id: Into<FieldId>>(self, id: Id) -> Self {
         self.world.crust.mantle(|mantle| {
             mantle.enqueue(Command::remove(id.into(), self.entity));
@@ -75,50 +105,109 @@ impl View<'_> {
 
     pub fn has<Id: Into<FieldId> + Copy>(self, field: Id) -> bool {
         self.world.crust.mantle(|Mantle { core, .. }| {
-            core.entity_location_locking(self.entity)
-                .filter(|location| core.archetype_has(field.into(), location.archetype))
+            core.entity_location(self.entity)
+                .filter(|location| core.entity_has(field.into(), self.entity, *location))
                 .is_some()
         })
     }
 
     /// Will panic if called in the middle of a flush
     pub fn get<T: Component>(&self) -> Option<ColumnReadGuard<'_, T>> {
+        self.try_get().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Will panic if called in the middle of a flush
+    pub fn get_mut<T: Component>(&self) -> Option<ColumnWriteGuard<'_, T>> {
+        self.try_get_mut().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like [`View::get`] but returns an error if the entity was despawned or a flush is running
+    pub fn try_get<T: Component>(&self) -> Result<Option<ColumnReadGuard<'_, T>>, Error> {
         let _ = T::NON_ZST_OR_PANIC;
-        Crust::begin_access(&self.world.crust.flush_guard);
+        self.get_mapped(T::id().into(), |bytes| {
+            // SAFETY: Don't TypeId check not needed because Entity id acts as TypeId
+            unsafe { (bytes.as_ptr() as *const T).as_ref() }.unwrap()
+        })
+    }
+
+    /// Like [`View::get_mut`] but returns an error if the entity was despawned or a flush is
+    /// running
+    pub fn try_get_mut<T: Component>(&self) -> Result<Option<ColumnWriteGuard<'_, T>>, Error> {
+        let _ = T::NON_ZST_OR_PANIC;
+        self.get_mapped_mut(T::id().into(), |bytes| {
+            // SAFETY: Don't TypeId check not needed because Entity id acts as TypeId
+            unsafe { (bytes.as_ptr() as *mut T).as_mut() }.unwrap()
+        })
+    }
+
+    /// Insert a component that may not have a Rust type. See [`World::register_component`].
+    /// Will panic on flush if `component` is not registered or `bytes` is the wrong size.
+    /// # Safety
+    /// `bytes` must be a valid value of `component`. Ownership of the value is moved into the world.
+    pub unsafe fn insert_raw(self, component: Entity, bytes: &[MaybeUninit<u8>]) -> Self {
+        self.world.crust.mantle(|mantle| {
+            mantle.enqueue(unsafe { Command::insert_raw(component, bytes.into(), self.entity) });
+        });
+        self
+    }
+
+    /// Get a component as type erased bytes.
+    /// Will panic if called in the middle of a flush
+    pub fn get_raw(&self, component: Entity) -> Option<ColumnReadGuard<'_, [MaybeUninit<u8>]>> {
+        self.get_mapped(component.into(), |bytes| bytes).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Get a component as mutable type erased bytes.
+    /// Will panic if called in the middle of a flush
+    pub fn get_raw_mut(
+        &self,
+        component: Entity,
+    ) -> Option<ColumnWriteGuard<'_, [MaybeUninit<u8>]>> {
+        self.get_mapped_mut(component.into(), |bytes| bytes).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    fn get_mapped<U: ?Sized>(
+        &self,
+        field: FieldId,
+        func: impl FnOnce(&[MaybeUninit<u8>]) -> &U,
+    ) -> Result<Option<ColumnReadGuard<'_, U>>, Error> {
+        Crust::try_begin_access(&self.world.crust.flush_guard)?;
         // SAFETY: World aliasing is temporary
         let core = unsafe { &self.world.crust.mantle.get().as_ref().unwrap().core };
-        let location = core.entity_location_locking(self.entity).unwrap();
-        let out = core.get_bytes(T::id().into(), location).map(|bytes| {
+        let Some(location) = core.entity_location(self.entity) else {
+            Crust::end_access(&self.world.crust.flush_guard);
+            return Err(Error::EntityNotFound(self.entity));
+        };
+        let out = core.get_bytes(field, self.entity, location).map(|bytes| {
             ColumnReadGuard::new(
-                MappedRwLockReadGuard::map(bytes, |bytes| {
-                    // SAFETY: Don't TypeId check not needed because Entity id acts as TypeId
-                    unsafe { (bytes.as_ptr() as *const T).as_ref() }.unwrap()
-                }),
+                MappedRwLockReadGuard::map(bytes, func),
                 &self.world.crust.flush_guard,
             )
         });
         Crust::end_access(&self.world.crust.flush_guard);
-        out
+        Ok(out)
     }
 
-    /// Will panic if called in the middle of a flush
-    pub fn get_mut<T: Component>(&self) -> Option<ColumnWriteGuard<'_, T>> {
-        let _ = T::NON_ZST_OR_PANIC;
-        Crust::begin_access(&self.world.crust.flush_guard);
+    fn get_mapped_mut<U: ?Sized>(
+        &self,
+        field: FieldId,
+        func: impl FnOnce(&mut [MaybeUninit<u8>]) -> &mut U,
+    ) -> Result<Option<ColumnWriteGuard<'_, U>>, Error> {
+        Crust::try_begin_access(&self.world.crust.flush_guard)?;
         // SAFETY: World aliasing is temporary
         let core = unsafe { &self.world.crust.mantle.get().as_ref().unwrap().core };
-        let location = core.entity_location_locking(self.entity).unwrap();
-        let out = core.get_bytes_mut(T::id().into(), location).map(|bytes| {
+        let Some(location) = core.entity_location(self.entity) else {
+            Crust::end_access(&self.world.crust.flush_guard);
+            return Err(Error::EntityNotFound(self.entity));
+        };
+        let out = core.get_bytes_mut(field, self.entity, location).map(|bytes| {
             ColumnWriteGuard::new(
-                MappedRwLockWriteGuard::map(bytes, |bytes| {
-                    // SAFETY: Don't TypeId check not needed because Entity id acts as TypeId
-                    unsafe { (bytes.as_ptr() as *mut T).as_mut() }.unwrap()
-                }),
+                MappedRwLockWriteGuard::map(bytes, func),
                 &self.world.crust.flush_guard,
             )
         });
         Crust::end_access(&self.world.crust.flush_guard);
-        out
+        Ok(out)
     }
 
     pub fn fields<Q: AccessTuple>(&self) -> Q::Out {
@@ -135,73 +224,222 @@ impl View<'_> {
         destination
     }
 
-    pub fn duplicate_into(&self, options: DupeOpts, destination: View) {
+    pub fn duplicate_into(&self, _options: DupeOpts, _destination: View) {
         todo!();
     }
 
+    /// Hide from queries without removing any components. Applied on the next flush.
+    pub fn disable(self) -> Self {
+        self.insert(Disabled)
+    }
+
+    pub fn enable(self) -> Self {
+        self.remove(Disabled::id())
+    }
+
+    pub fn is_enabled(self) -> bool {
+        !self.has(Disabled::id())
+    }
+
     pub fn despawn(self) {
         self.world.crust.mantle(|mantle| mantle.enqueue(Command::despawn(self.entity)));
     }
 }
 
-pub struct ColumnReadGuard<'a, T> {
+/// Prints the id, name & every component. Components without a `Debug` impl print their type name.
+/// Will deadlock if a component of the entity is borrowed mutably on the same thread.
+impl fmt::Debug for View<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.world.crust.mantle(|Mantle { core, .. }| {
+            let mut out = f.debug_struct("View");
+            out.field("id", &self.entity);
+            let Some(location) = core.entity_location(self.entity) else {
+                return out.finish_non_exhaustive();
+            };
+            // Columns are only try locked so a view can be printed while its values are borrowed
+            let name_field = FieldId::from(Name::id());
+            match core.try_get_bytes(name_field, self.entity, location) {
+                // SAFETY: Bytes are of a Name
+                Ok(Some(bytes)) => out.field(
+                    "name",
+                    &unsafe { (bytes.as_ptr() as *const Name).as_ref() }.unwrap().0,
+                ),
+                Ok(None) => &mut out,
+                Err(()) => out.field("name", &format_args!("<borrowed>")),
+            };
+
+            let mut fields: Vec<_> =
+                core.archetypes[location.archetype].signature.iter().copied().collect();
+            // A sparse set that is write locked might have the entity, so it's printed as borrowed
+            let mut sparse: Vec<_> = (core.sparse_sets.iter())
+                .filter(|(_, sparse_set)| {
+                    sparse_set
+                        .try_read()
+                        .is_none_or(|sparse_set| sparse_set.row(self.entity).is_some())
+                })
+                .map(|(field, _)| *field)
+                .collect();
+            sparse.sort();
+            fields.extend(sparse);
+
+            let mut components = Vec::new();
+            for field in fields.into_iter().filter(|field| *field != name_field) {
+                let Some(info) = core.component_info(field.component()) else {
+                    continue;
+                };
+                let target = field.target_index().map(|index| core.entity_at(index));
+                let value = core.try_get_bytes(field, self.entity, location);
+                components.push(FieldDebug { info, target, value });
+            }
+            out.field("components", &components).finish()
+        })
+    }
+}
+
+/// Component value or pair of a [`View`]'s debug output
+struct FieldDebug<'a> {
+    info: ComponentInfo,
+    target: Option<Option<Entity>>,
+    /// `Err` if the value is write locked
+    value: Result<Option<MappedRwLockReadGuard<'a, [MaybeUninit<u8>]>>, ()>,
+}
+
+impl fmt::Debug for FieldDebug<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = fmt::from_fn(|f| match (self.info.debug, &self.value) {
+            (_, Err(())) => write!(f, "{} <borrowed>", self.info.name),
+            // SAFETY: Bytes are of the component
+            (Some(debug), Ok(Some(bytes))) => unsafe { debug(bytes, f) },
+            // ZSTs have no column so use a dangling aligned pointer
+            (Some(debug), Ok(None)) if self.info.size == 0 => unsafe {
+                debug(
+                    std::slice::from_raw_parts(std::ptr::without_provenance(self.info.align), 0),
+                    f,
+                )
+            },
+            _ => f.write_str(self.info.name),
+        });
+        match self.target {
+            Some(Some(target)) => f.debug_tuple("").field(&value).field(&target).finish(),
+            Some(None) => f.debug_tuple("").field(&value).field(&"<despawned>").finish(),
+            None => value.fmt(f),
+        }
+    }
+}
+
+pub struct ColumnReadGuard<'a, T: ?Sized> {
     mapped_guard: MappedRwLockReadGuard<'a, T>,
-    flush_guard: *const AtomicUsize,
+    flush_guard: *const FlushGuard,
 }
 
-impl<'a, T> ColumnReadGuard<'a, T> {
+impl<'a, T: ?Sized> ColumnReadGuard<'a, T> {
     pub(crate) fn new(
         mapped_guard: MappedRwLockReadGuard<'a, T>,
-        flush_guard: &AtomicUsize,
+        flush_guard: &FlushGuard,
     ) -> Self {
         Crust::begin_access(flush_guard);
         Self { mapped_guard, flush_guard }
     }
 }
 
-impl<T> Deref for ColumnReadGuard<'_, T> {
+impl<T: ?Sized> Deref for ColumnReadGuard<'_, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         &self.mapped_guard
     }
 }
 
-impl<T> Drop for ColumnReadGuard<'_, T> {
+impl<T: ?Sized> Drop for ColumnReadGuard<'_, T> {
     fn drop(&mut self) {
         // SAFETY: Always safe because atomic
         Crust::end_access(unsafe { self.flush_guard.as_ref().unwrap() });
     }
 }
 
-pub struct ColumnWriteGuard<'a, T> {
+pub struct ColumnWriteGuard<'a, T: ?Sized> {
     mapped_guard: MappedRwLockWriteGuard<'a, T>,
-    flush_guard: *const AtomicUsize,
+    flush_guard: *const FlushGuard,
 }
 
-impl<'a, T> ColumnWriteGuard<'a, T> {
+impl<'a, T: ?Sized> ColumnWriteGuard<'a, T> {
     pub(crate) fn new(
         mapped_guard: MappedRwLockWriteGuard<'a, T>,
-        flush_guard: &AtomicUsize,
+        flush_guard: &FlushGuard,
     ) -> Self {
         Crust::begin_access(flush_guard);
         Self { mapped_guard, flush_guard }
     }
 }
 
-impl<T> Deref for ColumnWriteGuard<'_, T> {
+impl<T: ?Sized> Deref for ColumnWriteGuard<'_, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         &self.mapped_guard
     }
 }
 
-impl<T> DerefMut for ColumnWriteGuard<'_, T> {
+impl<T: ?Sized> DerefMut for ColumnWriteGuard<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.mapped_guard
     }
 }
 
-impl<T> Drop for ColumnWriteGuard<'_, T> {
+impl<T: ?Sized> Drop for ColumnWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: Always safe because atomic
+        Crust::end_access(unsafe { self.flush_guard.as_ref().unwrap() });
+    }
+}
+
+/// Mutable borrows of a component on several entities. See [`crate::query::Query::get_many_mut`].
+/// Values are only borrowed through the guard so they can't outlive its column locks.
+///
+/// ```compile_fail
+/// # use ssecs::prelude::*;
+/// # use ssecs_macros::Component;
+/// # #[derive(Component)]
+/// # struct Health(u32);
+/// # let world = World::new();
+/// # let a = world.spawn().insert(Health(1)).id();
+/// # let b = world.spawn().insert(Health(2)).id();
+/// # world.flush();
+/// let query = world.query().term().write(Health::id()).build();
+/// let escaped: &mut Health = {
+///     let mut many = query.get_many_mut::<Health, 2>([a, b]).unwrap().unwrap();
+///     let [a, _] = many.each_mut();
+///     a
+/// };
+/// ```
+pub struct ManyWriteGuard<'a, T, const N: usize> {
+    values: [*mut T; N],
+    _columns: Vec<MappedRwLockWriteGuard<'a, Column>>,
+    flush_guard: *const FlushGuard,
+}
+
+impl<'a, T, const N: usize> ManyWriteGuard<'a, T, N> {
+    /// Takes over a read already begun by the caller, which is ended on drop.
+    /// Values must be valid & distinct while `columns` are locked.
+    pub(crate) fn new(
+        values: [*mut T; N],
+        columns: Vec<MappedRwLockWriteGuard<'a, Column>>,
+        flush_guard: &FlushGuard,
+    ) -> Self {
+        Self { values, _columns: columns, flush_guard }
+    }
+
+    pub fn each_ref(&self) -> [&T; N] {
+        // SAFETY: Values are valid while the columns are locked
+        self.values.map(|value| unsafe { &*value })
+    }
+
+    /// Borrow every value at once, e.g. `let [a, b] = many.each_mut();`
+    pub fn each_mut(&mut self) -> [&mut T; N] {
+        // SAFETY: Values are distinct & valid while the columns are locked
+        self.values.map(|value| unsafe { &mut *value })
+    }
+}
+
+impl<T, const N: usize> Drop for ManyWriteGuard<'_, T, N> {
     fn drop(&mut self) {
         // SAFETY: Always safe because atomic
         Crust::end_access(unsafe { self.flush_guard.as_ref().unwrap() });
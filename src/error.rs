@@ -0,0 +1,77 @@
+use std::fmt;
+
+use crate::entity::Entity;
+
+/// Returned by the `try_*` variants of world access instead of panicking
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The entity was never spawned or has been despawned
+    EntityNotFound(Entity),
+    /// The world is being flushed so it can't be read
+    FlushInProgress,
+    /// Something is reading the world (query, column guard, etc.) so it can't be flushed
+    ReadersActive,
+    /// The entity is not a registered component. See [`crate::world::World::register_component`].
+    ComponentNotRegistered(Entity),
+    /// Bytes given for a component don't match its size
+    SizeMismatch { expected: usize, found: usize },
+    /// Another component was registered with the same stable id
+    StableIdTaken(&'static str),
+    /// Query text couldn't be parsed. `position` is the byte offset of the term.
+    InvalidQuery {
+        position: usize,
+        reason: &'static str,
+    },
+    /// [`crate::query::Query::single`] matched no entities
+    NoMatches,
+    /// [`crate::query::Query::single`] matched more than one entity
+    MultipleMatches,
+    /// The entity exists but isn't matched by the query
+    QueryMismatch(Entity),
+    /// The same entity was given more than once where distinct entities are required
+    DuplicateEntity(Entity),
+    /// The component isn't written or included by a term of the query
+    ComponentNotInQuery(Entity),
+    /// [`crate::world::Snapshot`] can't be restored into this world
+    InvalidSnapshot(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EntityNotFound(entity) => write!(f, "Entity {entity} does not exist"),
+            Self::FlushInProgress => write!(f, "Tried to read while structurally mutating"),
+            Self::ReadersActive => write!(f, "Tried to structurally mutate while reading"),
+            Self::ComponentNotRegistered(entity) => {
+                write!(f, "Component {entity} is not registered")
+            }
+            Self::SizeMismatch { expected, found } => {
+                write!(f, "Expected {expected} bytes for component but got {found}")
+            }
+            Self::StableIdTaken(name) => {
+                write!(
+                    f,
+                    "Stable id of {name} is already used by another component"
+                )
+            }
+            Self::InvalidQuery { position, reason } => {
+                write!(f, "Invalid query term at {position}: {reason}")
+            }
+            Self::NoMatches => write!(f, "Expected one entity to match the query but got none"),
+            Self::MultipleMatches => {
+                write!(f, "Expected one entity to match the query but got several")
+            }
+            Self::QueryMismatch(entity) => write!(f, "Entity {entity} does not match the query"),
+            Self::DuplicateEntity(entity) => write!(f, "Entity {entity} was given more than once"),
+            Self::ComponentNotInQuery(component) => {
+                write!(
+                    f,
+                    "Component {component} is not written or included by the query"
+                )
+            }
+            Self::InvalidSnapshot(reason) => write!(f, "Invalid snapshot: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,188 @@
+use crate::{
+    self as ssecs,
+    component::Component,
+    entity::{Entity, View},
+    slotmap::Key,
+    world::{World, archetype::FieldId, command::Command, core::Core},
+};
+use ssecs_macros::*;
+
+/// Relationship to a parent entity. Use with [`View::child_of`].
+#[derive(Component)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChildOf;
+
+/// Name of an entity. Only needs to be unique among siblings. See [`World::lookup`].
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Name(pub String);
+
+impl Name {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+}
+
+impl View<'_> {
+    pub fn named(self, name: impl Into<String>) -> Self {
+        self.insert(Name::new(name))
+    }
+
+    /// Replaces the current parent. Applied on the next flush.
+    pub fn child_of(self, parent: Entity) -> Self {
+        // Parents are removed when applied so earlier calls in the same flush are replaced too
+        self.world.crust.mantle(|mantle| {
+            mantle.enqueue(Command::remove_relationship(ChildOf::id(), self.entity));
+        });
+        self.insert_pair(ChildOf, parent)
+    }
+
+    pub fn parent(&self) -> Option<Entity> {
+        self.world.crust.mantle(|mantle| mantle.core.target(self.entity, ChildOf::id()))
+    }
+
+    pub fn children(&self) -> Vec<Entity> {
+        let field = FieldId::pair(ChildOf::id(), self.entity);
+        self.world.crust.mantle(|mantle| mantle.core.entities_with(field).collect())
+    }
+}
+
+impl World {
+    /// Find an entity by the names of it & its ancestors separated by `/`, e.g. `"level/room/door"`.
+    /// The first name is of an entity without a parent.
+    pub fn lookup(&self, path: &str) -> Option<Entity> {
+        self.crust.mantle(|mantle| {
+            let core = &mantle.core;
+            let mut names = path.split('/');
+            let root = names.next()?;
+            let mut current = core.entities_with(Name::id().into()).find(|entity| {
+                core.name_is(*entity, root) && core.target(*entity, ChildOf::id()).is_none()
+            })?;
+            for name in names {
+                current = core
+                    .entities_with(FieldId::pair(ChildOf::id(), current))
+                    .find(|entity| core.name_is(*entity, name))?;
+            }
+            Some(current)
+        })
+    }
+
+    /// Find any entity with a name
+    pub fn entity_by_name(&self, name: &str) -> Option<Entity> {
+        self.crust.mantle(|mantle| {
+            let core = &mantle.core;
+            core.entities_with(Name::id().into()).find(|entity| core.name_is(*entity, name))
+        })
+    }
+}
+
+impl Core {
+    /// Entity currently using an index
+    pub(crate) fn entity_at(&self, index: u32) -> Option<Entity> {
+        let slot = self.entity_index.slots.get(index as usize)?;
+        slot.data.map(|_| Entity::from(Key { index, generation: slot.generation }))
+    }
+
+    /// Target of the first pair of a relationship on an entity
+    pub(crate) fn target(&self, entity: Entity, relationship: Entity) -> Option<Entity> {
+        self.targets(entity, relationship).next()
+    }
+
+    /// Targets of every pair of a relationship on an entity
+    pub(crate) fn targets(
+        &self,
+        entity: Entity,
+        relationship: Entity,
+    ) -> impl Iterator<Item = Entity> {
+        (self.pairs(entity, relationship)).filter_map(|field| self.entity_at(field.target_index()?))
+    }
+
+    /// Every pair of a relationship on an entity
+    pub(crate) fn pairs(
+        &self,
+        entity: Entity,
+        relationship: Entity,
+    ) -> impl Iterator<Item = FieldId> {
+        let relationship = FieldId::from(relationship);
+        (self.entity_location(entity).into_iter())
+            .flat_map(|location| self.archetypes[location.archetype].signature.iter().copied())
+            .filter(move |field| {
+                field.is_pair() && FieldId::from(field.component()) == relationship
+            })
+    }
+
+    /// Targets of `relationship` starting from the entity's, then its target's & so on.
+    /// Stops after visiting every entity once in case of cycles.
+    pub(crate) fn ancestors(
+        &self,
+        entity: Entity,
+        relationship: Entity,
+    ) -> impl Iterator<Item = Entity> {
+        std::iter::successors(self.target(entity, relationship), move |current| {
+            self.target(*current, relationship)
+        })
+        .take(self.entity_index.slots.len())
+    }
+
+    /// Entities with a field in their archetype
+    pub(crate) fn entities_with(&self, field: FieldId) -> impl Iterator<Item = Entity> {
+        (self.field_index.get(&field).into_iter())
+            .flat_map(|field_locations| field_locations.keys())
+            .flat_map(|archetype| self.archetypes[*archetype].entities.iter().copied())
+    }
+
+    fn name_is(&self, entity: Entity, name: &str) -> bool {
+        let Some(location) = self.entity_location(entity) else {
+            return false;
+        };
+        self.get_bytes(Name::id().into(), entity, location).is_some_and(|bytes| {
+            // SAFETY: Bytes are of a Name
+            let value = unsafe { (bytes.as_ptr() as *const Name).as_ref() }.unwrap();
+            value.0 == name
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let world = World::new();
+        let level = world.spawn().named("level");
+        let room = world.spawn().named("room").child_of(level.id());
+        let door = world.spawn().named("door").child_of(room.id());
+        let other = world.spawn().named("other");
+        world.spawn().named("door").child_of(other.id());
+        world.flush();
+
+        assert_eq!(Some(room.id()), door.parent());
+        assert_eq!(vec![door.id()], room.children());
+        assert_eq!(Some(door.id()), world.lookup("level/room/door"));
+        assert_eq!(Some(level.id()), world.lookup("level"));
+        assert_eq!(None, world.lookup("room"));
+        assert_eq!(None, world.lookup("level/door"));
+        assert_eq!(Some(room.id()), world.entity_by_name("room"));
+
+        // Reparenting replaces the old parent
+        door.child_of(level.id());
+        world.flush();
+        assert_eq!(Some(door.id()), world.lookup("level/door"));
+        assert!(room.children().is_empty());
+
+        // Only the last parent set before a flush is kept
+        door.child_of(room.id()).child_of(other.id());
+        world.flush();
+        let parents: Vec<_> =
+            world.crust.mantle(|mantle| mantle.core.pairs(door.id(), ChildOf::id()).collect());
+        assert_eq!(vec![FieldId::pair(ChildOf::id(), other.id())], parents);
+        assert!(room.children().is_empty());
+
+        // Children of despawned entities lose the relationship
+        level.despawn();
+        world.flush();
+        assert_eq!(None, room.parent());
+        assert_eq!(Some(room.id()), world.lookup("room"));
+    }
+}
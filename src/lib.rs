@@ -3,10 +3,14 @@
 
 pub mod component;
 pub mod entity;
+mod error;
+pub mod hierarchy;
 pub mod query;
 mod slotmap;
 pub mod world;
 
+pub use error::Error;
+
 trait NonZstOrPanic: Sized {
     #[allow(missing_docs)]
     const NON_ZST_OR_PANIC: () = {
@@ -21,6 +25,7 @@ impl<T> NonZstOrPanic for T {}
 pub mod prelude {
     pub use crate::component::Component;
     pub use crate::entity::Entity;
+    pub use crate::hierarchy::{ChildOf, Name};
     pub use crate::query::Query;
     pub use crate::world::World;
 }
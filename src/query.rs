@@ -1,11 +1,28 @@
+use std::{
+    cmp,
+    collections::HashMap,
+    mem::MaybeUninit,
+    sync::{Arc, atomic::Ordering},
+};
+
+use parking_lot::Mutex;
+
 use crate as ssecs;
 use crate::{
-    component::Component,
-    entity::{Entity, View},
-    world::World,
+    Error, NonZstOrPanic,
+    component::{Component, Disabled},
+    entity::{Entity, ManyWriteGuard, View},
+    slotmap::Key,
+    world::{
+        Crust, World,
+        archetype::{Archetype, ArchetypeId, FieldId},
+        core::Core,
+    },
 };
 use ssecs_macros::*;
 
+mod dsl;
+
 pub trait AccessTuple {
     type Out;
 }
@@ -18,6 +35,12 @@ pub enum Access {
     Exclude,
     Read,
     Write,
+    /// Include entities whose value was inserted since the query last ran
+    Added,
+    /// Include entities whose value was inserted or mutably accessed since the query last ran
+    Changed,
+    /// Match entities with or without the field
+    Optional,
 }
 
 impl Access {
@@ -28,55 +51,597 @@ impl Access {
 
 #[derive(Clone)]
 struct Term {
+    /// Raw [`FieldId`]
     field: u64,
     access: Access,
+    /// Relationship to ancestors that can also satisfy the term
+    up: Option<Entity>,
+    /// Variable the term is matched on instead of the iterated entity
+    src_var: Option<usize>,
+    /// Variable that is the target of a pair. `field` is the relationship.
+    target_var: Option<usize>,
 }
 
 impl Default for Term {
     fn default() -> Self {
-        Self { field: 0, access: Access::Noop }
+        Self { field: 0, access: Access::Noop, up: None, src_var: None, target_var: None }
+    }
+}
+
+fn has_relationship(archetype: &Archetype, relationship: FieldId) -> bool {
+    (archetype.signature.iter())
+        .any(|field| field.is_pair() && FieldId::from(field.component()) == relationship)
+}
+
+impl Term {
+    fn field(&self) -> FieldId {
+        FieldId(self.field)
+    }
+
+    fn has_vars(&self) -> bool {
+        self.src_var.is_some() || self.target_var.is_some()
+    }
+
+    /// Sparse fields aren't in archetype signatures so they're checked per entity.
+    /// Terms on variables are checked by [`Query::bind`].
+    fn matches_archetype(&self, core: &Core, archetype: &Archetype) -> bool {
+        let field = self.field();
+        if self.src_var.is_some() {
+            return true;
+        }
+        if self.target_var.is_some() {
+            return has_relationship(archetype, field);
+        }
+        let has_ancestor = |relationship| has_relationship(archetype, FieldId::from(relationship));
+        match self.access {
+            Access::Noop | Access::Optional => true,
+            Access::Exclude => !archetype.signature.contains(field),
+            _ => {
+                archetype.signature.contains(field)
+                    || core.sparse_sets.contains_key(&field)
+                    || self.up.is_some_and(has_ancestor)
+            }
+        }
+    }
+
+    /// Zero sized components have no ticks so `Added` & `Changed` only match them on the first run
+    fn matches_entity(&self, core: &Core, entity: Entity, last_run: Option<u64>) -> bool {
+        if self.has_vars() {
+            return true;
+        }
+        if let Some(relationship) = self.up {
+            return self.matches_up(core, entity, relationship, last_run);
+        }
+        let field = self.field();
+        if let Some(sparse_set) = core.sparse_sets.get(&field) {
+            let contains = sparse_set.read().contains(entity);
+            let matched = match self.access {
+                Access::Noop | Access::Optional => true,
+                Access::Exclude => !contains,
+                _ => contains,
+            };
+            if !matched {
+                return false;
+            }
+        }
+        let (Access::Added | Access::Changed, Some(last_run)) = (self.access, last_run) else {
+            return true;
+        };
+        let ticks =
+            (core.entity_location(entity)).and_then(|location| core.ticks(field, entity, location));
+        match (self.access, ticks) {
+            (Access::Added, Some(ticks)) => ticks.added > last_run,
+            (Access::Changed, Some(ticks)) => ticks.changed > last_run,
+            _ => false,
+        }
+    }
+
+    /// Match against the closest of the entity & its ancestors with the field
+    fn matches_up(
+        &self,
+        core: &Core,
+        entity: Entity,
+        relationship: Entity,
+        last_run: Option<u64>,
+    ) -> bool {
+        let field = self.field();
+        let source =
+            std::iter::once(entity).chain(core.ancestors(entity, relationship)).find(|entity| {
+                let location = core.entity_location(*entity).unwrap();
+                core.entity_has(field, *entity, location)
+            });
+        match self.access {
+            Access::Noop | Access::Optional => true,
+            Access::Exclude => source.is_none(),
+            _ => source.is_some_and(|source| {
+                let term = Self { up: None, ..self.clone() };
+                term.matches_entity(core, source, last_run)
+            }),
+        }
+    }
+
+    /// Match an entity found through a variable, ignoring the term's variables
+    fn matches_on(&self, core: &Core, entity: Entity, last_run: Option<u64>) -> bool {
+        let Some(location) = core.entity_location(entity) else {
+            return false;
+        };
+        let term = Self { src_var: None, target_var: None, ..self.clone() };
+        term.matches_archetype(core, &core.archetypes[location.archetype])
+            && term.matches_entity(core, entity, last_run)
+    }
+}
+
+/// Entities bound to query variables for one match. See [`Query::run_vars`].
+pub struct Vars<'a> {
+    names: &'a [String],
+    values: &'a [Option<Entity>],
+}
+
+impl Vars<'_> {
+    pub fn get(&self, name: &str) -> Option<Entity> {
+        let n = self.names.iter().position(|var| var == name)?;
+        self.values[n]
     }
 }
 
-#[derive(Component)]
+/// Archetypes matched by a query. Recomputed when archetypes are created or deleted.
+#[derive(Component, Default)]
 struct QueryState {
-    // TODO
+    generation: Option<u64>,
+    archetypes: Vec<ArchetypeId>,
+    /// Change tick when the query last ran. Used by `Added` & `Changed` terms.
+    last_run: Option<u64>,
+    /// Entities of matched archetypes in [`OrderBy`] order
+    sorted: Vec<Entity>,
+    /// Sum of the versions of matched archetypes & ordered columns when sorted
+    sorted_version: Option<u64>,
+    /// Matched archetypes by the target index of their `group_by` pairs
+    groups: HashMap<u32, Vec<ArchetypeId>>,
+}
+
+/// Type erased comparison of a component's values
+#[derive(Clone)]
+struct OrderBy {
+    field: FieldId,
+    cmp: Arc<dyn Fn(&[MaybeUninit<u8>], &[MaybeUninit<u8>]) -> cmp::Ordering + Send + Sync>,
 }
 
 pub struct Query {
     world: World,
     terms: Vec<Term>,
+    /// Match entities with [`Disabled`]
+    match_disabled: bool,
+    order_by: Option<OrderBy>,
+    /// Relationship archetypes are grouped by the target of
+    group_by: Option<Entity>,
+    /// Relationship to parents that are run before their children
+    cascade: Option<Entity>,
+    /// Names of variables used by terms
+    vars: Vec<String>,
+    state: Mutex<QueryState>,
 }
 
-trait QueryClosure {
-    fn run(self, query: &Query, state: &QueryState);
-}
+impl Query {
+    /// Run `func` for every matched entity.
+    /// Structural changes made in `func` are applied on the next flush.
+    /// Will panic if the query is already running.
+    /// Entities are run once even if there are multiple ways to bind the query's variables.
+    pub fn run(&self, mut func: impl FnMut(View<'_>)) {
+        self.run_archetypes(None, false, |view, _| func(view));
+    }
 
-impl<F: FnMut(View<'_>)> QueryClosure for F {
-    fn run(self, query: &Query, state: &QueryState) {}
-}
+    /// Like [`Query::run`] but `func` is run for every way the query's variables can be bound
+    pub fn run_vars(&self, func: impl FnMut(View<'_>, &Vars<'_>)) {
+        self.run_archetypes(None, true, func);
+    }
 
-impl Query {
-    fn run<F: QueryClosure>(&self, func: F) {
-        let cache = QueryState {}; // TODO
-        func.run(self, &cache);
+    /// Like [`Query::run`] but only for entities with a `group_by` pair targeting `target`.
+    /// Will panic if the query has no `group_by`.
+    pub fn run_group(&self, target: Entity, mut func: impl FnMut(View<'_>)) {
+        if self.group_by.is_none() {
+            panic!("Query has no `group_by`");
+        }
+        self.run_archetypes(Some(target), false, |view, _| func(view));
+    }
+
+    /// Targets of the `group_by` relationship of matched entities
+    pub fn groups(&self) -> Vec<Entity> {
+        self.world.crust.mantle(|mantle| {
+            let core = &mantle.core;
+            let Some(mut state) = self.state.try_lock() else {
+                panic!("Query is already running");
+            };
+            self.update_state(core, &mut state);
+            let mut targets: Vec<_> = state.groups.keys().copied().collect();
+            targets.sort();
+            targets.into_iter().filter_map(|index| core.entity_at(index)).collect()
+        })
+    }
+
+    /// Number of matched entities. Counted from archetype sizes unless terms have to be checked
+    /// per entity (sparse fields, `up`, variables, or `Added` & `Changed` after the first run).
+    /// Doesn't count as a run for `Added` & `Changed`.
+    pub fn count(&self) -> usize {
+        self.inspect(|core, archetypes, last_run| {
+            let entities = archetypes.iter().map(|id| &core.archetypes[*id].entities);
+            if !self.filters_entities(core, last_run) {
+                return entities.map(Vec::len).sum();
+            }
+            (entities.flatten())
+                .filter(|entity| self.matches_entity(core, **entity, last_run))
+                .count()
+        })
+    }
+
+    /// Whether no entities are matched. See [`Query::count`].
+    pub fn is_empty(&self) -> bool {
+        self.inspect(|core, archetypes, last_run| {
+            let mut entities = archetypes.iter().flat_map(|id| &core.archetypes[*id].entities);
+            match self.filters_entities(core, last_run) {
+                true => !entities.any(|entity| self.matches_entity(core, *entity, last_run)),
+                false => entities.next().is_none(),
+            }
+        })
+    }
+
+    /// The only matched entity
+    pub fn single(&self) -> Result<View<'_>, Error> {
+        let entity = self.inspect(|core, archetypes, last_run| {
+            let mut entities = (archetypes.iter())
+                .flat_map(|id| &core.archetypes[*id].entities)
+                .filter(|entity| self.matches_entity(core, **entity, last_run));
+            match (entities.next(), entities.next()) {
+                (Some(entity), None) => Ok(*entity),
+                (None, _) => Err(Error::NoMatches),
+                (Some(_), Some(_)) => Err(Error::MultipleMatches),
+            }
+        })?;
+        Ok(View { entity, world: &self.world })
+    }
+
+    /// `entity` if it's matched by the query
+    pub fn get(&self, entity: Entity) -> Result<View<'_>, Error> {
+        self.inspect(|core, archetypes, last_run| {
+            self.check_entity(core, archetypes, last_run, entity)?;
+            Ok(View { entity, world: &self.world })
+        })
+    }
+
+    /// Mutably borrow `T` on several matched entities at once, e.g. for collision response.
+    /// Entities sharing a column lock it once instead of deadlocking like [`View::get_mut`] would.
+    /// `T` must be written or included by a term of the query.
+    /// `None` if an entity doesn't have `T`, e.g. it's matched through an ancestor.
+    /// Errors if an entity is repeated, despawned or not matched, or a flush is running.
+    pub fn get_many_mut<T: Component, const N: usize>(
+        &self,
+        entities: [Entity; N],
+    ) -> Result<Option<ManyWriteGuard<'_, T, N>>, Error> {
+        let _ = T::NON_ZST_OR_PANIC;
+        if let Some((n, _)) =
+            (entities.iter().enumerate()).find(|(n, entity)| entities[..*n].contains(entity))
+        {
+            return Err(Error::DuplicateEntity(entities[n]));
+        }
+        let field = FieldId::from(T::id());
+        if !self.terms.iter().any(|term| {
+            term.field == field.0
+                && matches!(term.access, Access::Write | Access::Include)
+                && term.src_var.is_none()
+        }) {
+            return Err(Error::ComponentNotInQuery(T::id()));
+        }
+        let flush_guard = &self.world.crust.flush_guard;
+        Crust::try_begin_access(flush_guard)?;
+        // SAFETY: World aliasing is temporary
+        let core = unsafe { &self.world.crust.mantle.get().as_ref().unwrap().core };
+        let checked = match self.state.try_lock() {
+            Some(mut state) => {
+                self.update_state(core, &mut state);
+                (entities.iter()).try_for_each(|e| {
+                    self.check_entity(core, &state.archetypes, state.last_run, *e)
+                })
+            }
+            None => {
+                Crust::end_access(flush_guard);
+                panic!("Query is already running");
+            }
+        };
+        let out = checked.map(|()| {
+            core.get_many_bytes_mut(field, entities).map(|(columns, bytes)| {
+                // Entity id acts as TypeId & entities are distinct so rows don't alias
+                ManyWriteGuard::new(bytes.map(|bytes| bytes as *mut T), columns, flush_guard)
+            })
+        });
+        // The guard takes over the read
+        if !matches!(out, Ok(Some(_))) {
+            Crust::end_access(flush_guard);
+        }
+        out
+    }
+
+    fn check_entity(
+        &self,
+        core: &Core,
+        archetypes: &[ArchetypeId],
+        last_run: Option<u64>,
+        entity: Entity,
+    ) -> Result<(), Error> {
+        let location = core.entity_location(entity).ok_or(Error::EntityNotFound(entity))?;
+        match archetypes.contains(&location.archetype)
+            && self.matches_entity(core, entity, last_run)
+        {
+            true => Ok(()),
+            false => Err(Error::QueryMismatch(entity)),
+        }
+    }
+
+    /// Run `func` with the matched archetypes & when the query last ran
+    fn inspect<R>(&self, func: impl FnOnce(&Core, &[ArchetypeId], Option<u64>) -> R) -> R {
+        self.world.crust.mantle(|mantle| {
+            let core = &mantle.core;
+            let Some(mut state) = self.state.try_lock() else {
+                panic!("Query is already running");
+            };
+            self.update_state(core, &mut state);
+            func(core, &state.archetypes, state.last_run)
+        })
+    }
+
+    /// Whether entities of matched archetypes can still fail to match
+    fn filters_entities(&self, core: &Core, last_run: Option<u64>) -> bool {
+        !self.vars.is_empty()
+            || self.terms.iter().any(|term| {
+                term.up.is_some()
+                    || core.sparse_sets.contains_key(&term.field())
+                    || (last_run.is_some()
+                        && matches!(term.access, Access::Added | Access::Changed))
+            })
+    }
+
+    /// Whether an entity of a matched archetype matches every term & the variables can be bound
+    fn matches_entity(&self, core: &Core, entity: Entity, last_run: Option<u64>) -> bool {
+        if !self.terms.iter().all(|term| term.matches_entity(core, entity, last_run)) {
+            return false;
+        }
+        let mut values = vec![None; self.vars.len()];
+        self.vars.is_empty() || self.bind(core, entity, 0, &mut values, last_run, &mut |_| true)
+    }
+
+    fn run_archetypes(
+        &self,
+        group: Option<Entity>,
+        each_binding: bool,
+        mut func: impl FnMut(View<'_>, &Vars<'_>),
+    ) {
+        self.world.crust.mantle(|mantle| {
+            let core = &mantle.core;
+            let Some(mut state) = self.state.try_lock() else {
+                panic!("Query is already running");
+            };
+            self.update_state(core, &mut state);
+            let last_run = state.last_run;
+            state.last_run = Some(core.change_tick.fetch_add(1, Ordering::Relaxed));
+            if let Some(order_by) = &self.order_by {
+                self.update_order(core, &mut state, order_by);
+            }
+
+            let archetypes = match group {
+                Some(target) => state.groups.get(&Key::from(target).index).map_or(&[][..], |g| g),
+                None => &state.archetypes,
+            };
+            let mut visit = |entity| {
+                if !self.terms.iter().all(|term| term.matches_entity(core, entity, last_run)) {
+                    return;
+                }
+                let view = View { entity, world: &self.world };
+                if self.vars.is_empty() {
+                    func(view, &Vars { names: &[], values: &[] });
+                    return;
+                }
+                let mut values = vec![None; self.vars.len()];
+                self.bind(core, entity, 0, &mut values, last_run, &mut |values| {
+                    func(view, &Vars { names: &self.vars, values });
+                    !each_binding
+                });
+            };
+            if self.order_by.is_none() && self.cascade.is_none() {
+                for id in archetypes {
+                    core.archetypes[*id].entities.iter().copied().for_each(&mut visit);
+                }
+                return;
+            }
+
+            let mut entities: Vec<_> = match self.order_by {
+                Some(_) => (state.sorted.iter().copied())
+                    .filter(|entity| {
+                        let archetype = core.entity_location(*entity).unwrap().archetype;
+                        group.is_none() || archetypes.contains(&archetype)
+                    })
+                    .collect(),
+                None => (archetypes.iter())
+                    .flat_map(|id| core.archetypes[*id].entities.iter().copied())
+                    .collect(),
+            };
+            // Breadth first. Stable so entities on the same level keep their order.
+            if let Some(relationship) = self.cascade {
+                entities.sort_by_cached_key(|entity| core.ancestors(*entity, relationship).count());
+            }
+            entities.into_iter().for_each(visit);
+        });
+    }
+
+    /// Bind variables of terms from `n` onwards by joining on the entities & pairs that satisfy
+    /// each term. `found` is run for each complete binding & returns `true` to stop.
+    fn bind(
+        &self,
+        core: &Core,
+        this: Entity,
+        n: usize,
+        values: &mut Vec<Option<Entity>>,
+        last_run: Option<u64>,
+        found: &mut dyn FnMut(&[Option<Entity>]) -> bool,
+    ) -> bool {
+        let Some((n, term)) = self.terms.iter().enumerate().skip(n).find(|(_, t)| t.has_vars())
+        else {
+            return found(values);
+        };
+        let field = term.field();
+        let unbound_src = term.src_var.filter(|var| values[*var].is_none());
+        let sources: Vec<_> = match (term.src_var, unbound_src) {
+            (None, _) => vec![this],
+            (Some(var), None) => vec![values[var].unwrap()],
+            // Checked by `QueryBuilder::build` to be a field that can be looked up
+            (Some(_), Some(_)) => {
+                let mut sources: Vec<_> = core.entities_with(field).collect();
+                if let Some(sparse_set) = core.sparse_sets.get(&field) {
+                    sources.extend(sparse_set.read().entities.iter().copied());
+                }
+                sources
+            }
+        };
+        for source in sources {
+            if let Some(var) = unbound_src {
+                values[var] = Some(source);
+            }
+            let stop = match term.target_var {
+                None => {
+                    term.matches_on(core, source, last_run)
+                        && self.bind(core, this, n + 1, values, last_run, found)
+                }
+                Some(var) => core.targets(source, field.component()).any(|target| {
+                    let previous = values[var];
+                    if previous.is_some_and(|previous| previous != target) {
+                        return false;
+                    }
+                    values[var] = Some(target);
+                    let stop = self.bind(core, this, n + 1, values, last_run, found);
+                    values[var] = previous;
+                    stop
+                }),
+            };
+            if let Some(var) = unbound_src {
+                values[var] = None;
+            }
+            if stop {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn matches_archetype(&self, core: &Core, archetype: &Archetype) -> bool {
+        if !self.match_disabled && archetype.signature.contains(Disabled::id().into()) {
+            return false;
+        }
+        self.terms.iter().all(|term| term.matches_archetype(core, archetype))
+    }
+
+    fn update_state(&self, core: &Core, state: &mut QueryState) {
+        if state.generation == Some(core.archetype_generation) {
+            return;
+        }
+        state.generation = Some(core.archetype_generation);
+        state.archetypes = (core.archetypes.iter())
+            .filter(|(_, archetype)| self.matches_archetype(core, archetype))
+            .map(|(id, _)| id)
+            .collect();
+        state.sorted_version = None;
+
+        state.groups.clear();
+        let Some(relationship) = self.group_by.map(FieldId::from) else {
+            return;
+        };
+        for id in state.archetypes.iter().copied() {
+            let targets = (core.archetypes[id].signature.iter())
+                .filter(|field| field.is_pair() && FieldId::from(field.component()) == relationship)
+                .filter_map(|field| field.target_index());
+            for target in targets {
+                state.groups.entry(target).or_default().push(id);
+            }
+        }
+    }
+
+    /// Sort again if entities were added to or removed from matched archetypes or the ordered
+    /// column changed
+    fn update_order(&self, core: &Core, state: &mut QueryState, order_by: &OrderBy) {
+        let field = order_by.field;
+        let mut version = (state.archetypes.iter()).fold(0_u64, |sum, id| {
+            sum.wrapping_add(core.archetypes[*id].version)
+        });
+        let mut columns = HashMap::new();
+        match core.sparse_sets.get(&field) {
+            Some(sparse_set) => version = version.wrapping_add(sparse_set.read().column.version()),
+            None => {
+                for id in state.archetypes.iter() {
+                    let table = core.archetypes[*id].table;
+                    if let Some(column) = core.column(field, *id) {
+                        columns.entry(table).or_insert_with(|| column.read());
+                    }
+                }
+                version = (columns.values())
+                    .fold(version, |sum, column| sum.wrapping_add(column.version()));
+            }
+        }
+        if state.sorted_version == Some(version) {
+            return;
+        }
+        state.sorted_version = Some(version);
+
+        let mut sorted: Vec<_> = (state.archetypes.iter())
+            .flat_map(|id| core.archetypes[*id].entities.iter().copied())
+            .collect();
+        match core.sparse_sets.get(&field) {
+            Some(sparse_set) => {
+                let sparse_set = sparse_set.read();
+                sorted.retain(|entity| sparse_set.contains(*entity));
+                let bytes = |entity| sparse_set.column.get_chunk(sparse_set.row(entity).unwrap());
+                sorted.sort_by(|a, b| (order_by.cmp)(bytes(*a), bytes(*b)));
+            }
+            None => {
+                let bytes = |entity| {
+                    let location = core.entity_location(entity).unwrap();
+                    let table = core.archetypes[location.archetype].table;
+                    columns[&table].get_chunk(location.table_row)
+                };
+                sorted.sort_by(|a, b| (order_by.cmp)(bytes(*a), bytes(*b)));
+            }
+        }
+        state.sorted = sorted;
     }
 }
 
 impl Clone for Query {
     fn clone(&self) -> Self {
-        Self { terms: self.terms.clone(), world: World { crust: self.world.crust.clone() } }
+        Self {
+            terms: self.terms.clone(),
+            world: World { crust: self.world.crust.clone() },
+            match_disabled: self.match_disabled,
+            order_by: self.order_by.clone(),
+            group_by: self.group_by,
+            cascade: self.cascade,
+            vars: self.vars.clone(),
+            state: Default::default(),
+        }
     }
 }
 
 pub struct QueryBuilder {
     query: Query,
-    cursor: usize,
 }
 
 impl QueryBuilder {
     pub(crate) fn new(world: World) -> Self {
-        Self { cursor: 0, query: Query { world, terms: Vec::new() } }
+        let query = Query {
+            world,
+            terms: Vec::new(),
+            match_disabled: false,
+            order_by: None,
+            group_by: None,
+            cascade: None,
+            vars: Vec::new(),
+            state: Default::default(),
+        };
+        Self { query }
     }
 
     pub fn term(mut self) -> Self {
@@ -84,43 +649,213 @@ impl QueryBuilder {
         self
     }
 
-    pub fn incl(mut self, component: Entity) -> Self {
+    pub fn incl(mut self, field: impl Into<FieldId>) -> Self {
         let Some(term) = self.query.terms.last_mut() else {
             panic!("Must create term before calling `incl`");
         };
         term.access = Access::Include;
-        term.field = component.raw();
+        term.field = field.into().0;
         self
     }
 
-    pub fn excl(mut self, component: Entity) -> Self {
+    pub fn excl(mut self, field: impl Into<FieldId>) -> Self {
         let Some(term) = self.query.terms.last_mut() else {
             panic!("Must create term before calling `excl`");
         };
         term.access = Access::Exclude;
-        term.field = component.raw();
+        term.field = field.into().0;
         self
     }
 
-    pub fn read(mut self, component: Entity) -> Self {
+    pub fn read(mut self, field: impl Into<FieldId>) -> Self {
         let Some(term) = self.query.terms.last_mut() else {
             panic!("Must create term before calling `read`");
         };
         term.access = Access::Read;
-        term.field = component.raw();
+        term.field = field.into().0;
         self
     }
 
-    pub fn write(mut self, component: Entity) -> Self {
+    pub fn write(mut self, field: impl Into<FieldId>) -> Self {
         let Some(term) = self.query.terms.last_mut() else {
             panic!("Must create term before calling `write`");
         };
         term.access = Access::Write;
-        term.field = component.raw();
+        term.field = field.into().0;
+        self
+    }
+
+    /// Only match entities whose value was inserted since the query last ran.
+    /// Matches every entity with the field on the first run. Removals are read with
+    /// [`World::removal_reader`].
+    /// Will panic if the field is a tag, which has no values to track.
+    pub fn added(mut self, field: impl Into<FieldId>) -> Self {
+        let field = field.into();
+        self.assert_has_values(field, "added");
+        let Some(term) = self.query.terms.last_mut() else {
+            panic!("Must create term before calling `added`");
+        };
+        term.access = Access::Added;
+        term.field = field.0;
+        self
+    }
+
+    /// Only match entities whose value was inserted or mutably accessed since the query last ran.
+    /// Matches every entity with the field on the first run.
+    /// Will panic if the field is a tag, which has no values to track.
+    pub fn changed(mut self, field: impl Into<FieldId>) -> Self {
+        let field = field.into();
+        self.assert_has_values(field, "changed");
+        let Some(term) = self.query.terms.last_mut() else {
+            panic!("Must create term before calling `changed`");
+        };
+        term.access = Access::Changed;
+        term.field = field.0;
+        self
+    }
+
+    fn assert_has_values(&self, field: FieldId, filter: &str) {
+        let info =
+            (self.query.world.crust).mantle(|mantle| mantle.core.component_info(field.component()));
+        if let Some(info) = info
+            && info.size == 0
+        {
+            panic!("`{filter}` can't be used with tag `{}`", info.name);
+        }
+    }
+
+    /// Run in the order of `T`'s values. Adds a term including `T`.
+    /// The order is cached until entities of matched archetypes or the values of `T` change.
+    pub fn order_by<T: Component>(
+        mut self,
+        cmp: impl Fn(&T, &T) -> cmp::Ordering + Send + Sync + 'static,
+    ) -> Self {
+        let _ = T::NON_ZST_OR_PANIC;
+        let field = FieldId::from(T::id());
+        self.query.terms.push(Term {
+            field: field.0,
+            access: Access::Include,
+            ..Default::default()
+        });
+        self.query.order_by = Some(OrderBy {
+            field,
+            cmp: Arc::new(move |a, b| {
+                // SAFETY: Bytes are of the ordered component
+                let [a, b] = [a, b].map(|bytes| unsafe { &*(bytes.as_ptr() as *const T) });
+                cmp(a, b)
+            }),
+        });
+        self
+    }
+
+    /// Match entities with or without `field`
+    pub fn optional(mut self, field: impl Into<FieldId>) -> Self {
+        let Some(term) = self.query.terms.last_mut() else {
+            panic!("Must create term before calling `optional`");
+        };
+        term.access = Access::Optional;
+        term.field = field.into().0;
         self
     }
 
-    pub fn build(self) -> Query {
+    /// Include pairs of `relationship` on the last term's source & bind their targets to `var`.
+    /// If `var` is already bound the target must be the same entity.
+    pub fn var_pair(mut self, relationship: Entity, var: &str) -> Self {
+        let var = self.var(var);
+        let Some(term) = self.query.terms.last_mut() else {
+            panic!("Must create term before calling `var_pair`");
+        };
+        term.access = Access::Include;
+        term.field = FieldId::from(relationship).0;
+        term.target_var = Some(var);
+        self
+    }
+
+    /// Match the last term on the entity bound to `var` instead of the iterated entity.
+    /// If `var` isn't bound by an earlier term it is bound to each entity with the term's field.
+    pub fn src(mut self, var: &str) -> Self {
+        let var = self.var(var);
+        let Some(term) = self.query.terms.last_mut() else {
+            panic!("Must create term before calling `src`");
+        };
+        term.src_var = Some(var);
+        self
+    }
+
+    fn var(&mut self, name: &str) -> usize {
+        let vars = &mut self.query.vars;
+        vars.iter().position(|var| var == name).unwrap_or_else(|| {
+            vars.push(name.to_string());
+            vars.len() - 1
+        })
+    }
+
+    /// Let the last term also be satisfied by the closest ancestor through `relationship`
+    /// that has the field, e.g. `.term().read(Transform::id()).up(ChildOf::id())`
+    pub fn up(mut self, relationship: Entity) -> Self {
+        let Some(term) = self.query.terms.last_mut() else {
+            panic!("Must create term before calling `up`");
+        };
+        term.up = Some(relationship);
+        self
+    }
+
+    /// Run entities in breadth first order of the `relationship` hierarchy so targets are run
+    /// before the entities pointing to them
+    pub fn cascade(mut self, relationship: Entity) -> Self {
+        self.query.cascade = Some(relationship);
+        self
+    }
+
+    /// Group matched archetypes by the target of their `relationship` pairs.
+    /// See [`Query::run_group`].
+    pub fn group_by(mut self, relationship: Entity) -> Self {
+        self.query.group_by = Some(relationship);
+        self
+    }
+
+    /// Also match entities with [`Disabled`], which are skipped by default.
+    /// Queries with a term on [`Disabled`] always match them.
+    pub fn with_disabled(mut self) -> Self {
+        self.query.match_disabled = true;
+        self
+    }
+
+    /// Returns the index of the first term that uses a variable as its source without being able
+    /// to bind it
+    fn check_vars(&self) -> Result<(), usize> {
+        let mut bound = vec![false; self.query.vars.len()];
+        for (n, term) in self.query.terms.iter().enumerate() {
+            if let Some(var) = term.src_var.filter(|var| !bound[*var]) {
+                let binds = matches!(term.access, Access::Include | Access::Read | Access::Write)
+                    && term.target_var.is_none()
+                    && term.up.is_none();
+                if !binds {
+                    return Err(n);
+                }
+                bound[var] = true;
+            }
+            if let Some(var) = term.target_var {
+                bound[var] = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Will panic if a variable is first used as the source of a term that can't bind it
+    pub fn build(mut self) -> Query {
+        if let Err(n) = self.check_vars() {
+            let var = self.query.terms[n].src_var.unwrap();
+            panic!(
+                "Variable `{}` must be bound before this term",
+                self.query.vars[var]
+            );
+        }
+
+        let disabled = FieldId::from(Disabled::id()).0;
+        if (self.query.terms.iter()).any(|term| !term.access.is_noop() && term.field == disabled) {
+            self.query.match_disabled = true;
+        }
         self.query
     }
 }
@@ -128,7 +863,7 @@ impl QueryBuilder {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::component::{Component, tests::*};
+    use crate::component::Component;
 
     #[derive(Component)]
     struct Byte(u8);
@@ -156,9 +891,356 @@ mod test {
         let query = world
             .query()
             .term().incl(Byte::id())
-            .build()
-            .run(|view: View<'_>| {
-                view.get_mut::<Byte>().unwrap().0 += 1;
-            });
+            .build();
+        query.run(|view: View<'_>| {
+            view.get_mut::<Byte>().unwrap().0 += 1;
+        });
+
+        let mut sum = 0;
+        query.run(|view| sum += view.get::<Byte>().unwrap().0);
+        assert_eq!(6, sum);
+
+        let mut count = 0;
+        world.query().term().incl(A::id()).term().excl(B::id()).build().run(|_| count += 1);
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn change_detection() {
+        let world = World::new();
+        let a = world.spawn().insert(Byte(0));
+        let b = world.spawn().insert(Byte(0));
+        world.flush();
+
+        let run = |query: &Query| {
+            let mut entities = Vec::new();
+            query.run(|view| entities.push(view.id()));
+            entities
+        };
+        let added = world.query().term().added(Byte::id()).build();
+        let changed = world.query().term().changed(Byte::id()).build();
+        assert_eq!(2, run(&added).len());
+        assert_eq!(2, run(&changed).len());
+        assert!(run(&added).is_empty());
+        assert!(run(&changed).is_empty());
+
+        // Reading doesn't count as a change
+        let _ = a.get::<Byte>().unwrap().0;
+        b.get_mut::<Byte>().unwrap().0 = 1;
+        assert!(run(&added).is_empty());
+        assert_eq!(vec![b.id()], run(&changed));
+
+        // Overwriting changes without adding, moving archetypes keeps ticks
+        a.insert(Byte(2));
+        b.insert(A);
+        let c = world.spawn().insert(Byte(3));
+        world.flush();
+        assert_eq!(vec![c.id()], run(&added));
+        let mut changed_entities = run(&changed);
+        changed_entities.sort_by_key(|entity| entity.raw());
+        assert_eq!(vec![a.id(), c.id()], changed_entities);
+        assert!(run(&changed).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be used with tag")]
+    fn change_detection_tag() {
+        World::new().query().term().added(A::id());
+    }
+
+    #[test]
+    fn order_by() {
+        let world = World::new();
+        let values = [3, 1, 2];
+        let entities: Vec<_> =
+            values.iter().map(|value| world.spawn().insert(Byte(*value)).id()).collect();
+        world.entity(entities[2]).insert(A);
+        world.flush();
+
+        let query = world.query().order_by::<Byte>(|a, b| a.0.cmp(&b.0)).build();
+        let run = |query: &Query| {
+            let mut values = Vec::new();
+            query.run(|view| values.push(view.get::<Byte>().unwrap().0));
+            values
+        };
+        assert_eq!(vec![1, 2, 3], run(&query));
+        let version = query.state.lock().sorted_version;
+        assert_eq!(vec![1, 2, 3], run(&query));
+        assert_eq!(version, query.state.lock().sorted_version);
+
+        // Values changing resorts
+        world.entity(entities[0]).get_mut::<Byte>().unwrap().0 = 0;
+        assert_eq!(vec![0, 1, 2], run(&query));
+
+        // Entities moving between matched archetypes resorts
+        world.entity(entities[1]).insert(A);
+        world.spawn().insert(Byte(5)).insert(B);
+        world.flush();
+        assert_eq!(vec![0, 1, 2, 5], run(&query));
+
+        let filtered = world.query().term().incl(A::id()).order_by::<Byte>(|a, b| b.0.cmp(&a.0));
+        assert_eq!(vec![2, 1], run(&filtered.build()));
+    }
+
+    #[test]
+    fn group_by() {
+        use crate::hierarchy::ChildOf;
+
+        let world = World::new();
+        let [level_a, level_b] = [(); 2].map(|_| world.spawn().id());
+        world.spawn().insert(Byte(1)).child_of(level_a);
+        world.spawn().insert(Byte(2)).insert(A).child_of(level_a);
+        world.spawn().insert(Byte(3)).child_of(level_b);
+        world.spawn().insert(Byte(4));
+        world.flush();
+
+        let query = world.query().term().incl(Byte::id()).group_by(ChildOf::id()).build();
+        let run_group = |query: &Query, target| {
+            let mut sum = 0;
+            query.run_group(target, |view| sum += view.get::<Byte>().unwrap().0);
+            sum
+        };
+        assert_eq!(vec![level_a, level_b], query.groups());
+        assert_eq!(3, run_group(&query, level_a));
+        assert_eq!(3, run_group(&query, level_b));
+        assert_eq!(0, run_group(&query, world.spawn().id()));
+
+        let ordered =
+            world.query().order_by::<Byte>(|a, b| b.0.cmp(&a.0)).group_by(ChildOf::id()).build();
+        let mut values = Vec::new();
+        ordered.run_group(level_a, |view| values.push(view.get::<Byte>().unwrap().0));
+        assert_eq!(vec![2, 1], values);
+    }
+
+    #[test]
+    fn hierarchy() {
+        use crate::hierarchy::ChildOf;
+
+        let world = World::new();
+        let root = world.spawn().insert(Byte(1)).insert(A);
+        let child = world.spawn().insert(Byte(2)).child_of(root.id());
+        let grandchild = world.spawn().insert(Byte(3)).child_of(child.id());
+        world.spawn().insert(Byte(4));
+        world.flush();
+        // Reparent so a child is spawned before its parent: root -> grandchild -> child
+        grandchild.child_of(root.id());
+        child.child_of(grandchild.id());
+        world.flush();
+
+        let run = |query: Query| {
+            let mut values = Vec::new();
+            query.run(|view| values.push(view.get::<Byte>().unwrap().0));
+            values
+        };
+        let up = world.query().term().incl(Byte::id()).term().incl(A::id()).up(ChildOf::id());
+        let mut values = run(up.build());
+        values.sort();
+        assert_eq!(vec![1, 2, 3], values);
+
+        let not_up = world.query().term().incl(Byte::id()).term().excl(A::id()).up(ChildOf::id());
+        assert_eq!(vec![4], run(not_up.build()));
+
+        let cascade = world.query().term().incl(Byte::id()).cascade(ChildOf::id()).build();
+        let values = run(cascade);
+        let position = |value| values.iter().position(|v| *v == value).unwrap();
+        assert!(position(1) < position(3) && position(3) < position(2));
+        assert!(position(4) < position(3));
+    }
+
+    #[test]
+    fn variables() {
+        #[derive(Component)]
+        struct Likes;
+
+        #[derive(Component)]
+        struct Eats;
+
+        #[derive(Component)]
+        struct Habitable;
+
+        let world = World::new();
+        let [apples, pears] = [(); 2].map(|_| world.spawn().id());
+        let planet = world.spawn().insert(Habitable).id();
+        let barren = world.spawn().id();
+        let bob = world.spawn().insert_pair(Likes, apples).insert_pair(Likes, pears);
+        bob.insert_pair(Eats, pears).insert_pair(Eats, apples).insert_pair(A, planet);
+        let alice = world.spawn().insert_pair(Likes, apples).insert_pair(Eats, pears);
+        alice.insert_pair(A, barren);
+        world.flush();
+
+        // (Likes, $X), (Eats, $X)
+        let query = (world.query())
+            .term()
+            .var_pair(Likes::id(), "X")
+            .term()
+            .var_pair(Eats::id(), "X")
+            .build();
+        let mut matches = Vec::new();
+        query.run_vars(|view, vars| matches.push((view.id(), vars.get("X").unwrap())));
+        matches.sort_by_key(|(_, x)| x.raw());
+        assert_eq!(vec![(bob.id(), apples), (bob.id(), pears)], matches);
+        let mut count = 0;
+        query.run(|_| count += 1);
+        assert_eq!(1, count);
+
+        // (A, $Planet), Habitable($Planet)
+        let query = (world.query())
+            .term()
+            .var_pair(A::id(), "Planet")
+            .term()
+            .incl(Habitable::id())
+            .src("Planet")
+            .build();
+        let mut matches = Vec::new();
+        query.run_vars(|view, vars| matches.push((view.id(), vars.get("Planet").unwrap())));
+        assert_eq!(vec![(bob.id(), planet)], matches);
+
+        // Unbound sources are joined on every entity with the field
+        let query = (world.query())
+            .term()
+            .incl(Habitable::id())
+            .src("Planet")
+            .term()
+            .incl(FieldId::pair(A::id(), planet))
+            .build();
+        let mut matches = Vec::new();
+        query.run_vars(|view, vars| matches.push((view.id(), vars.get("Planet").unwrap())));
+        assert_eq!(vec![(bob.id(), planet)], matches);
+    }
+
+    #[test]
+    fn disabled() {
+        let world = World::new();
+        let a = world.spawn().insert(Byte(0));
+        world.spawn().insert(Byte(0)).insert(A);
+        world.flush();
+
+        let count = |query: &Query| {
+            let mut count = 0;
+            query.run(|_| count += 1);
+            count
+        };
+        let query = world.query().term().incl(Byte::id()).build();
+        let with_disabled = world.query().term().incl(Byte::id()).with_disabled().build();
+        let only_disabled = world.query().term().incl(Disabled::id()).build();
+        assert_eq!(2, count(&query));
+
+        a.disable();
+        world.flush();
+        assert!(!a.is_enabled());
+        assert_eq!(0, a.get::<Byte>().unwrap().0);
+        assert_eq!(1, count(&query));
+        assert_eq!(2, count(&with_disabled));
+        assert_eq!(1, count(&only_disabled));
+
+        a.enable();
+        world.flush();
+        assert_eq!(2, count(&query));
+        assert_eq!(0, count(&only_disabled));
+    }
+
+    #[test]
+    fn count_single_get() {
+        let world = World::new();
+        let a = world.spawn().insert(Byte(0)).insert(A);
+        let b = world.spawn().insert(Byte(1));
+        world.spawn().insert(Byte(2)).insert(B);
+        world.flush();
+
+        let bytes = world.query().term().incl(Byte::id()).build();
+        let only_a = world.query().term().incl(A::id()).build();
+        let none = world.query().term().incl(A::id()).term().incl(B::id()).build();
+        assert_eq!(3, bytes.count());
+        assert!(!bytes.is_empty());
+        assert!(none.is_empty());
+
+        assert_eq!(a.id(), only_a.single().unwrap().id());
+        assert_eq!(
+            Err(Error::MultipleMatches),
+            bytes.single().map(|view| view.id())
+        );
+        assert_eq!(Err(Error::NoMatches), none.single().map(|view| view.id()));
+
+        assert_eq!(1, bytes.get(b.id()).unwrap().get::<Byte>().unwrap().0);
+        assert_eq!(
+            Err(Error::QueryMismatch(b.id())),
+            only_a.get(b.id()).map(|view| view.id())
+        );
+        b.despawn();
+        world.flush();
+        assert_eq!(
+            Err(Error::EntityNotFound(b.id())),
+            bytes.get(b.id()).map(|view| view.id())
+        );
+
+        // Filtered per entity once the query has run
+        let changed = world.query().term().changed(Byte::id()).build();
+        assert_eq!(2, changed.count());
+        changed.run(|_| {});
+        assert!(changed.is_empty());
+        a.get_mut::<Byte>().unwrap().0 += 1;
+        assert_eq!(a.id(), changed.single().unwrap().id());
+        assert_eq!(1, changed.count());
+    }
+
+    #[test]
+    fn get_many_mut() {
+        #[derive(Component)]
+        struct Wide(#[allow(dead_code)] u16);
+
+        let world = World::new();
+        let a = world.spawn().insert(Byte(1));
+        let b = world.spawn().insert(Byte(2));
+        let c = world.spawn().insert(Byte(3)).insert(A).insert(Wide(0));
+        let d = world.spawn().insert(A);
+        world.flush();
+        let table = |e: &View| {
+            world.crust.mantle(|mantle| {
+                let core = &mantle.core;
+                core.archetypes[core.entity_location(e.id()).unwrap().archetype].table
+            })
+        };
+        assert_ne!(table(&a), table(&c));
+
+        let query = world.query().term().incl(A::id()).term().write(Byte::id()).build();
+        let optional = world.query().term().incl(A::id()).term().optional(Byte::id()).build();
+        let bytes = world.query().term().incl(Byte::id()).build();
+        {
+            // Same column
+            let mut many = bytes.get_many_mut::<Byte, 2>([a.id(), b.id()]).unwrap().unwrap();
+            let [a, b] = many.each_mut();
+            std::mem::swap(&mut a.0, &mut b.0);
+        }
+        {
+            // Different tables
+            let mut many = bytes.get_many_mut::<Byte, 2>([c.id(), a.id()]).unwrap().unwrap();
+            let [c, a] = many.each_mut();
+            c.0 += a.0;
+            a.0 *= 10;
+            assert_eq!([5, 20], many.each_ref().map(|value| value.0));
+        }
+        assert_eq!([20, 1, 5], [a, b, c].map(|e| e.get::<Byte>().unwrap().0));
+
+        let many = |query: &Query, entities| {
+            query
+                .get_many_mut::<Byte, 2>(entities)
+                .map(|many| many.map(|many| many.each_ref().map(|value| value.0)))
+        };
+        assert_eq!(
+            Err(Error::DuplicateEntity(a.id())),
+            many(&bytes, [a.id(), a.id()])
+        );
+        assert_eq!(
+            Err(Error::QueryMismatch(a.id())),
+            many(&query, [c.id(), a.id()])
+        );
+        assert_eq!(
+            Err(Error::QueryMismatch(d.id())),
+            many(&query, [c.id(), d.id()])
+        );
+        assert_eq!(
+            Err(Error::ComponentNotInQuery(Byte::id())),
+            many(&optional, [c.id(), d.id()])
+        );
     }
 }
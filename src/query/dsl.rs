@@ -0,0 +1,206 @@
+use crate::{
+    Error,
+    component::ComponentInfo,
+    entity::Entity,
+    query::{Query, QueryBuilder},
+    world::{FieldId, World},
+};
+
+enum Prefix {
+    None,
+    Not,
+    Optional,
+}
+
+impl World {
+    /// Build a query from text, e.g. `"Position, Velocity, !Frozen, ?Sprite"`.
+    /// - `Name` includes, `!Name` excludes & `?Name` optionally matches a component
+    /// - `(Relationship, Target)` is a pair. The target is an entity path (see [`World::lookup`])
+    ///   or a `$Variable`.
+    /// - `Name($Variable)` matches a component on the entity bound to a variable
+    ///
+    /// Components are found by their full type name, or without the module path & generic
+    /// arguments if that is unique.
+    pub fn query_str(&self, text: &str) -> Result<Query, Error> {
+        let components = self.crust.mantle(|mantle| mantle.core.component_infos());
+        let mut builder = self.query();
+        let mut positions = Vec::new();
+        for (position, term) in split_terms(text) {
+            let invalid = |reason| Error::InvalidQuery { position, reason };
+            let resolve = |name: &str| resolve(&components, name.trim()).map_err(invalid);
+            let (prefix, body) = match term.as_bytes().first() {
+                Some(b'!') => (Prefix::Not, term[1..].trim_start()),
+                Some(b'?') => (Prefix::Optional, term[1..].trim_start()),
+                _ => (Prefix::None, term),
+            };
+            if body.is_empty() {
+                return Err(invalid("empty term"));
+            }
+            builder = builder.term();
+
+            if let Some(pair) = body.strip_prefix('(') {
+                let pair = pair.strip_suffix(')').ok_or(invalid("expected `)`"))?;
+                let (relationship, target) =
+                    pair.split_once(',').ok_or(invalid("expected `,` in pair"))?;
+                let relationship = resolve(relationship)?;
+                let target = target.trim();
+                builder = match (target.strip_prefix('$'), prefix) {
+                    (Some(var), Prefix::None) => builder.var_pair(relationship, var),
+                    (Some(_), _) => {
+                        return Err(invalid("pairs with variables can't have a prefix"));
+                    }
+                    (None, prefix) => {
+                        let target = self.lookup(target).ok_or(invalid("unknown entity"))?;
+                        with_prefix(builder, prefix, FieldId::pair(relationship, target))
+                    }
+                };
+            } else {
+                let (name, src) = match body.split_once('(') {
+                    Some((name, src)) => {
+                        let src = (src.strip_suffix(')').map(str::trim))
+                            .and_then(|src| src.strip_prefix('$'))
+                            .ok_or(invalid("expected `($Variable)`"))?;
+                        (name, Some(src))
+                    }
+                    None => (body, None),
+                };
+                builder = with_prefix(builder, prefix, resolve(name)?.into());
+                if let Some(var) = src {
+                    builder = builder.src(var);
+                }
+            }
+            positions.push(position);
+        }
+        if let Err(n) = builder.check_vars() {
+            let reason = "variable must be bound by an earlier term";
+            return Err(Error::InvalidQuery { position: positions[n], reason });
+        }
+        Ok(builder.build())
+    }
+}
+
+fn with_prefix(builder: QueryBuilder, prefix: Prefix, field: FieldId) -> QueryBuilder {
+    match prefix {
+        Prefix::None => builder.incl(field),
+        Prefix::Not => builder.excl(field),
+        Prefix::Optional => builder.optional(field),
+    }
+}
+
+/// Terms separated by commas outside of parentheses, trimmed, with their byte offsets
+fn split_terms(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut depth = 0_usize;
+    let mut start = 0;
+    let mut terms = Vec::new();
+    for (n, char) in text.char_indices() {
+        match char {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                terms.push((start, &text[start..n]));
+                start = n + 1;
+            }
+            _ => {}
+        }
+    }
+    terms.push((start, &text[start..]));
+    (terms.into_iter())
+        .map(|(start, term)| (start + term.len() - term.trim_start().len(), term.trim()))
+        .filter(|(_, term)| !term.is_empty())
+}
+
+/// Find a component by its full type name, or by its name without the module path & generic
+/// arguments if unique
+fn resolve(components: &[ComponentInfo], name: &str) -> Result<Entity, &'static str> {
+    if let Some(info) = components.iter().find(|info| info.name == name) {
+        return Ok(info.id);
+    }
+    let mut matches = components.iter().filter(|info| short_name(info.name) == name);
+    match (matches.next(), matches.next()) {
+        (Some(info), None) => Ok(info.id),
+        (Some(_), Some(_)) => Err("ambiguous component name"),
+        (None, _) => Err("unknown component"),
+    }
+}
+
+/// Last path segment of a type name without generic arguments, e.g. `Baz` for `a::Baz<b::C>`
+fn short_name(name: &str) -> &str {
+    let path = name.split('<').next().unwrap_or(name);
+    path.rsplit("::").next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as ssecs;
+    use crate::component::Component;
+    use ssecs_macros::*;
+
+    #[derive(Component)]
+    struct Spot(#[allow(dead_code)] u8);
+
+    #[derive(Component)]
+    struct Frozen;
+
+    #[derive(Component)]
+    struct Sprite;
+
+    #[derive(Component)]
+    struct Docked;
+
+    #[derive(Component)]
+    struct Inhabited;
+
+    fn count(query: &Query) -> usize {
+        let mut count = 0;
+        query.run(|_| count += 1);
+        count
+    }
+
+    #[test]
+    fn query_str() {
+        let world = World::new();
+        world.spawn().insert(Spot(0));
+        world.spawn().insert(Spot(0)).insert(Sprite);
+        world.spawn().insert(Spot(0)).insert(Frozen);
+        let level = world.spawn().named("level").insert(Inhabited);
+        world.spawn().insert(Spot(0)).child_of(level.id()).insert_pair(Docked, level.id());
+        world.flush();
+
+        assert_eq!(
+            3,
+            count(&world.query_str("Spot, !Frozen, ?Sprite").unwrap())
+        );
+        assert_eq!(1, count(&world.query_str("Spot, Sprite").unwrap()));
+        let full_name = format!("{}, {}", Spot::info().name, Frozen::info().name);
+        assert_eq!(1, count(&world.query_str(&full_name).unwrap()));
+        assert_eq!(
+            1,
+            count(&world.query_str("Spot, (ChildOf, level)").unwrap())
+        );
+        let docked = world.query_str("(Docked, $Planet), Inhabited($Planet)").unwrap();
+        assert_eq!(1, count(&docked));
+
+        let error = |text| match world.query_str(text) {
+            Err(Error::InvalidQuery { position, reason }) => (position, reason),
+            _ => panic!("Expected an error for {text}"),
+        };
+        assert_eq!((6, "unknown component"), error("Spot, Missing"));
+        assert_eq!((0, "unknown entity"), error("(ChildOf, nowhere)"));
+        assert_eq!((0, "expected `)`"), error("(ChildOf, level"));
+        assert_eq!(
+            (6, "variable must be bound by an earlier term"),
+            error("Spot, !Sprite($X)")
+        );
+        assert_eq!(
+            (0, "pairs with variables can't have a prefix"),
+            error("!(Docked, $X)")
+        );
+
+        // Generic arguments are ignored by short names
+        world.register_component(ComponentInfo::new("dsl::Wrapper<dsl::Spot>", 0, 1));
+        assert_eq!(0, count(&world.query_str("Wrapper").unwrap()));
+        world.register_component(ComponentInfo::new("dsl::Wrapper<u8>", 0, 1));
+        assert_eq!((0, "ambiguous component name"), error("Wrapper"));
+    }
+}
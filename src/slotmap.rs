@@ -1,6 +1,7 @@
 use std::{
     marker::PhantomData,
     ops::{Index, IndexMut},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
@@ -31,10 +32,12 @@ impl<T> Default for Slot<T> {
     }
 }
 
-#[derive(Clone)]
 pub(crate) struct SlotMap<K, T> {
     pub(crate) slots: Vec<Slot<T>>,
     pub(crate) available: Vec<usize>,
+    /// Keys handed out by [`SlotMap::reserve`] since the last [`SlotMap::flush_reserved`].
+    /// Taken from the end of `available` first, then past the end of `slots`.
+    reserved: AtomicUsize,
     _phantom: PhantomData<K>,
 }
 
@@ -61,7 +64,20 @@ where
 
 impl<K, T> Default for SlotMap<K, T> {
     fn default() -> Self {
-        Self { slots: Vec::new(), available: Vec::new(), _phantom: PhantomData }
+        Self {
+            slots: Vec::new(),
+            available: Vec::new(),
+            reserved: AtomicUsize::new(0),
+            _phantom: PhantomData,
+        }
+    }
+}
+
+fn next_generation(generation: u32) -> u32 {
+    if generation != u32::MAX {
+        generation + 1
+    } else {
+        1
     }
 }
 
@@ -70,8 +86,50 @@ where
     K: Copy + From<Key>,
     Key: From<K>,
 {
+    /// Get a key that will be valid after [`SlotMap::flush_reserved`] without exclusive access
+    pub fn reserve(&self) -> K {
+        let n = self.reserved.fetch_add(1, Ordering::Relaxed);
+        let index = match self.available.len().checked_sub(n + 1) {
+            Some(free) => self.available[free],
+            None => self.slots.len() + n - self.available.len(),
+        };
+        if u32::MAX as usize <= index {
+            panic!("Reached slotmap limit");
+        }
+        let generation = self.slots.get(index).map_or(0, |slot| slot.generation);
+        K::from(Key { index: index as u32, generation: next_generation(generation) })
+    }
+
+    /// If a key was reserved but not flushed
+    pub fn is_reserved(&self, key: K) -> bool {
+        let key = Key::from(key);
+        let reserved = self.reserved.load(Ordering::Relaxed);
+        let free = reserved.min(self.available.len());
+        let index = key.index as usize;
+        let generation = self.slots.get(index).map_or(0, |slot| slot.generation);
+        let reserved_free = self.available[self.available.len() - free..].contains(&index);
+        let reserved_new = self.slots.len() <= index && index < self.slots.len() + reserved - free;
+        (reserved_free || reserved_new) && key.generation == next_generation(generation)
+    }
+
+    /// Insert `data` for every reserved key, in the order they were reserved
+    pub fn flush_reserved(&mut self, mut data: impl FnMut() -> T) {
+        for _ in 0..std::mem::take(self.reserved.get_mut()) {
+            self.insert_unchecked(data());
+        }
+    }
+
     /// Returns `None` if there are no more slots left
     pub fn insert(&mut self, data: T) -> K {
+        debug_assert_eq!(
+            *self.reserved.get_mut(),
+            0,
+            "Reserved keys must be flushed first"
+        );
+        self.insert_unchecked(data)
+    }
+
+    fn insert_unchecked(&mut self, data: T) -> K {
         let slot_index = if let Some(index) = self.available.pop() {
             index
         } else {
@@ -83,11 +141,7 @@ where
         };
         let slot = &mut self.slots[slot_index];
         slot.data = Some(data);
-        slot.generation = if slot.generation != u32::MAX {
-            slot.generation + 1
-        } else {
-            1
-        };
+        slot.generation = next_generation(slot.generation);
         K::from(Key { index: slot_index as u32, generation: slot.generation })
     }
 
@@ -104,15 +158,6 @@ where
         removed
     }
 
-    pub fn remove_ignore_generation(&mut self, key: K) -> Option<T> {
-        let key = Key::from(key);
-        let removed = self.slots.get_mut(key.index as usize).and_then(|slot| slot.data.take());
-        if removed.is_some() {
-            self.available.push(key.index as usize);
-        }
-        removed
-    }
-
     pub fn get(&self, key: K) -> Option<&T> {
         let key = Key::from(key);
         self.slots
@@ -133,8 +178,18 @@ where
             .and_then(|slot| slot.data.as_mut())
     }
 
-    pub fn get_mut_ignore_generation(&mut self, key: K) -> Option<&mut T> {
-        self.slots.get_mut(Key::from(key).index as usize).and_then(|slot| slot.data.as_mut())
+    pub fn iter(&self) -> impl Iterator<Item = (K, &T)> {
+        self.slots.iter().enumerate().filter_map(|(n, slot)| {
+            let key = Key { index: n as u32, generation: slot.generation };
+            slot.data.as_ref().map(|data| (K::from(key), data))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (K, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(n, slot)| {
+            let key = Key { index: n as u32, generation: slot.generation };
+            slot.data.as_mut().map(|data| (K::from(key), data))
+        })
     }
 
     pub fn disjoint<const N: usize>(&mut self, keys: [K; N]) -> Option<[&mut T; N]> {
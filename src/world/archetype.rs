@@ -2,10 +2,14 @@ use std::{collections::HashMap, mem::MaybeUninit};
 
 use aligned_vec::{AVec, RuntimeAlign};
 use derive_more::{Deref, DerefMut, From};
-use parking_lot::RwLock;
 use smallvec::SmallVec;
 
-use crate::{component::ComponentInfo, entity::Entity, slotmap::*};
+use crate::{
+    component::ComponentInfo,
+    entity::Entity,
+    slotmap::*,
+    world::table::{GrowthPolicy, TableId},
+};
 
 const ARCHETYPE_SAO: usize = 8;
 
@@ -29,21 +33,41 @@ pub(crate) struct ColumnIndex(pub usize);
 #[derive(Clone, Copy, Deref, DerefMut, Debug, PartialEq, Eq)]
 pub(crate) struct RowIndex(pub usize);
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub(crate) struct Archetype {
     pub signature: Signature,
+    pub table: TableId,
     pub entities: Vec<Entity>,
-    pub columns: Vec<RwLock<Column>>,
     pub edges: HashMap<FieldId, ArchetypeEdge>,
+    /// Consecutive flushes the archetype has had no entities
+    pub empty_flushes: usize,
+    /// Bumped when entities are added or removed
+    pub version: u64,
 }
 
 impl Archetype {
-    pub(crate) fn drop(&mut self, row: RowIndex) {
-        self.entities.swap_remove(*row);
-        for column in &mut self.columns {
-            column.get_mut().swap_drop(row);
+    pub(crate) fn new(signature: Signature, table: TableId) -> Self {
+        Self {
+            signature,
+            table,
+            entities: Default::default(),
+            edges: Default::default(),
+            empty_flushes: 0,
+            version: 0,
         }
     }
+
+    pub(crate) fn push(&mut self, entity: Entity) {
+        self.entities.push(entity);
+        self.version += 1;
+    }
+
+    /// Returns the entity moved into `row`
+    pub(crate) fn swap_remove(&mut self, row: RowIndex) -> Option<Entity> {
+        self.entities.swap_remove(*row);
+        self.version += 1;
+        self.entities.get(*row).copied()
+    }
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -52,7 +76,10 @@ pub(crate) struct ArchetypeEdge {
     pub remove: Option<ArchetypeId>,
 }
 
-/// Component or pair
+const PAIR: u64 = 1 << 63;
+
+/// Component or pair. Only entity indices are stored.
+/// Pairs are `PAIR | relationship << 32 | target`.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FieldId(pub u64);
 
@@ -63,9 +90,27 @@ impl From<Entity> for FieldId {
 }
 
 impl FieldId {
-    // TODO: Check for pairs
-    pub(crate) fn as_entity(&self) -> Option<Entity> {
-        Some(Entity::from_raw(self.0))
+    /// A relationship to a target entity. Uses the relationship's component info.
+    pub fn pair(relationship: Entity, target: Entity) -> Self {
+        let relationship = Key::from(relationship).index as u64;
+        Self(PAIR | relationship << 32 | Key::from(target).index as u64)
+    }
+
+    pub fn is_pair(self) -> bool {
+        self.0 & PAIR != 0
+    }
+
+    /// Component entity or the relationship of a pair, without generation
+    pub(crate) fn component(self) -> Entity {
+        match self.is_pair() {
+            true => Entity::from_raw((self.0 & !PAIR) >> 32),
+            false => Entity::from_raw(self.0),
+        }
+    }
+
+    /// Index of a pair's target entity
+    pub(crate) fn target_index(self) -> Option<u32> {
+        self.is_pair().then_some(self.0 as u32)
     }
 }
 
@@ -84,6 +129,10 @@ impl Signature {
         self.0.binary_search(&field).is_ok()
     }
 
+    pub fn position(&self, field: FieldId) -> Option<usize> {
+        self.0.binary_search(&field).ok()
+    }
+
     pub fn with(mut self, field: FieldId) -> Self {
         if let Err(n) = self.0.binary_search(&field) {
             self.0.insert(n, field);
@@ -132,29 +181,111 @@ impl Signature {
     }
 }
 
+/// Change ticks of a row. Compared against a query's last run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Ticks {
+    pub added: u64,
+    pub changed: u64,
+}
+
+impl Ticks {
+    pub fn new(tick: u64) -> Self {
+        Self { added: tick, changed: tick }
+    }
+}
+
+/// Zero sized components have no rows so they have no ticks
 #[derive(Debug)]
 pub(crate) struct Column {
     buffer: AVec<MaybeUninit<u8>, RuntimeAlign>,
+    ticks: Vec<Ticks>,
     info: ComponentInfo,
+    pub growth: GrowthPolicy,
+    /// Bumped when rows are added, removed, moved or mutably accessed
+    version: u64,
 }
 
 impl Column {
-    pub fn new(component_info: ComponentInfo) -> Self {
-        Self { buffer: AVec::new(component_info.align), info: component_info }
+    pub fn new(component_info: ComponentInfo, growth: GrowthPolicy) -> Self {
+        Self {
+            buffer: AVec::new(component_info.align),
+            ticks: Vec::new(),
+            info: component_info,
+            growth,
+            version: 0,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn info(&self) -> &ComponentInfo {
+        &self.info
+    }
+
+    /// Make room for `rows` more rows
+    pub fn reserve(&mut self, rows: usize) {
+        self.buffer.reserve_exact(rows * self.info.size);
+        if self.info.size != 0 {
+            self.ticks.reserve_exact(rows);
+        }
+    }
+
+    /// Rows that fit without reallocating
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity().checked_div(self.info.size).unwrap_or(usize::MAX)
+    }
+
+    /// Make room for `rows` more rows following the growth policy
+    fn grow(&mut self, rows: usize) {
+        let needed = self.no_chunks() + rows;
+        if needed <= self.capacity() {
+            return;
+        }
+        let grown = (self.capacity() as f32 * self.growth.factor) as usize;
+        self.reserve(needed.max(grown).max(self.growth.min_rows) - self.no_chunks());
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.buffer.shrink_to_fit();
+        self.ticks.shrink_to_fit();
     }
 
     fn swap_with_last(&mut self, RowIndex(row): RowIndex) {
+        self.version += 1;
         if row + 1 < self.no_chunks() {
             let (left, right) = self.buffer.split_at_mut((row + 1) * self.info.size);
-            left[row * self.info.size..].swap_with_slice(right);
+            let last = right.len() - self.info.size;
+            left[row * self.info.size..].swap_with_slice(&mut right[last..]);
+            let last = self.ticks.len() - 1;
+            self.ticks.swap(row, last);
         }
     }
 
     pub fn no_chunks(&self) -> usize {
-        if self.info.size == 0 {
-            0
-        } else {
-            self.buffer.len() / self.info.size
+        self.buffer.len().checked_div(self.info.size).unwrap_or(0)
+    }
+
+    /// # Safety
+    /// Bytes must be whole chunks of valid values for the column's component
+    pub unsafe fn extend_from_bytes(&mut self, bytes: &[MaybeUninit<u8>], tick: u64) {
+        debug_assert_eq!(bytes.len() % self.info.size.max(1), 0);
+        let rows = bytes.len().checked_div(self.info.size).unwrap_or(0);
+        self.grow(rows);
+        self.buffer.extend_from_slice(bytes);
+        self.ticks.resize(self.ticks.len() + rows, Ticks::new(tick));
+        self.version += 1;
+    }
+
+    pub fn ticks(&self, RowIndex(row): RowIndex) -> Option<Ticks> {
+        self.ticks.get(row).copied()
+    }
+
+    pub fn mark_changed(&mut self, RowIndex(row): RowIndex, tick: u64) {
+        if let Some(ticks) = self.ticks.get_mut(row) {
+            ticks.changed = tick;
+            self.version += 1;
         }
     }
 
@@ -166,17 +297,26 @@ impl Column {
         &mut self.buffer[row * self.info.size..][..self.info.size]
     }
 
-    pub unsafe fn write_into(&mut self, RowIndex(row): RowIndex, bytes: &[MaybeUninit<u8>]) {
+    pub unsafe fn write_into(
+        &mut self,
+        RowIndex(row): RowIndex,
+        bytes: &[MaybeUninit<u8>],
+        tick: u64,
+    ) {
         debug_assert_eq!(bytes.len(), self.info.size);
         if self.info.size == 0 {
             return;
         }
+        self.version += 1;
         if row < self.no_chunks() {
             // SAFETY: Chunk is written into
             unsafe { self.call_drop(RowIndex(row)) };
-            self.buffer[row * self.info.size..].copy_from_slice(bytes);
+            self.buffer[row * self.info.size..][..self.info.size].copy_from_slice(bytes);
+            self.ticks[row].changed = tick;
         } else {
+            self.grow(1);
             self.buffer.extend_from_slice(bytes);
+            self.ticks.push(Ticks::new(tick));
         }
     }
 
@@ -190,6 +330,7 @@ impl Column {
         self.swap_with_last(RowIndex(row));
 
         // Move last to other column
+        other.grow(1);
         other.buffer.resize(other.buffer.len() + other.info.size, MaybeUninit::zeroed());
         let n = self.buffer.len() - self.info.size;
         let m = other.buffer.len() - other.info.size;
@@ -197,6 +338,8 @@ impl Column {
 
         // Remove bytes old bytes
         self.buffer.truncate(n);
+        other.ticks.extend(self.ticks.pop());
+        other.version += 1;
     }
 
     // Must change length/overwrite bytes after call
@@ -208,20 +351,24 @@ impl Column {
         }
     }
 
-    pub fn shrink_to_fit(&mut self, target_chunks: usize) {
+    pub fn truncate(&mut self, target_chunks: usize) {
         for n in target_chunks..self.no_chunks() {
             // SAFETY: Shrunk after loop
             unsafe { self.call_drop(RowIndex(n)) };
         }
         self.buffer.truncate(target_chunks * self.info.size);
+        self.ticks.truncate(target_chunks);
+        self.version += 1;
     }
 
     pub fn swap_drop(&mut self, row: RowIndex) {
+        // Zero sized columns (e.g. of sparse tags) have no bytes to drop
+        if self.info.size == 0 {
+            return;
+        }
         self.swap_with_last(row);
         let n = self.buffer.len() / self.info.size - 1;
-        // SAFETY: Immediately shrunk
-        unsafe { self.call_drop(RowIndex(n)) };
-        self.shrink_to_fit(n);
+        self.truncate(n);
     }
 }
 
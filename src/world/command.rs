@@ -1,6 +1,7 @@
 use std::mem::{ManuallyDrop, MaybeUninit};
 
 use crate::{
+    Error,
     component::{Component, ComponentInfo},
     entity::Entity,
     world::{archetype::FieldId, core::Core},
@@ -14,58 +15,122 @@ use crate::{
 enum Operation {
     Noop,
     Spawn(Entity),
+    SpawnBatch {
+        entities: Vec<Entity>,
+        components: Vec<ComponentInfo>,
+        columns: Vec<Vec<MaybeUninit<u8>>>,
+    },
     Despawn(Entity),
     Insert {
+        field: FieldId,
         info: ComponentInfo,
         bytes: Box<[MaybeUninit<u8>]>,
         entity: Entity,
     },
+    InsertRaw {
+        component: Entity,
+        bytes: Box<[MaybeUninit<u8>]>,
+        entity: Entity,
+    },
     Remove {
         field: FieldId,
         entity: Entity,
     },
+    RemoveRelationship {
+        relationship: Entity,
+        entity: Entity,
+    },
 }
 
 #[derive(Debug)]
 pub(crate) struct Command {
     operation: Operation,
-    jump: usize,
 }
 
 unsafe impl Send for Command {}
 
 impl Default for Command {
     fn default() -> Self {
-        Self { operation: Operation::Noop, jump: 1 }
+        Self { operation: Operation::Noop }
+    }
+}
+
+/// Drop a value that wasn't inserted. Values of the wrong size are leaked.
+unsafe fn drop_rejected(info: ComponentInfo, bytes: &[MaybeUninit<u8>]) {
+    if bytes.len() == info.size {
+        // SAFETY: Bytes are an owned value of the component that was never moved into the world
+        unsafe { info.drop_unaligned(bytes) };
     }
 }
 
 impl Command {
     pub(crate) fn apply(self, core: &mut Core) {
+        if let Err(err) = self.try_apply(core) {
+            panic!("{err}");
+        }
+    }
+
+    pub(crate) fn try_apply(self, core: &mut Core) -> Result<(), Error> {
         use Operation::*;
         match self.operation {
             Noop => {}
             Spawn(entity) => {
                 core.initialize_entity_location(entity);
             }
+            SpawnBatch { entities, components, columns } => {
+                unsafe { core.spawn_batch(&entities, &components, &columns) }?;
+            }
             Despawn(entity) => {
-                core.despawn(entity);
+                core.despawn(entity)?;
+            }
+            Insert { field, info, bytes, entity } => {
+                let result = unsafe { core.try_insert_bytes(field, info, &bytes, entity) };
+                if result.is_err() {
+                    unsafe { drop_rejected(info, &bytes) };
+                }
+                result?;
             }
-            Insert { info, bytes, entity } => {
-                unsafe { core.insert_bytes(info, &bytes, entity) };
+            InsertRaw { component, bytes, entity } => {
+                // Values of unregistered components can't be dropped so they're leaked
+                let info = core
+                    .component_info(component)
+                    .ok_or(Error::ComponentNotRegistered(component))?;
+                let result =
+                    unsafe { core.try_insert_bytes(component.into(), info, &bytes, entity) };
+                if result.is_err() {
+                    unsafe { drop_rejected(info, &bytes) };
+                }
+                result?;
             }
             Remove { field, entity } => {
-                core.remove_field(field, entity);
+                core.try_remove_field(field, entity)?;
+            }
+            RemoveRelationship { relationship, entity } => {
+                let pairs: Vec<_> = core.pairs(entity, relationship).collect();
+                for field in pairs {
+                    core.try_remove_field(field, entity)?;
+                }
             }
         }
+        Ok(())
     }
 
     pub(crate) fn spawn(entity: Entity) -> Self {
-        Self { jump: 1, operation: Operation::Spawn(entity) }
+        Self { operation: Operation::Spawn(entity) }
+    }
+
+    /// # Safety
+    /// `columns` must hold a value of the matching component for every entity
+    pub(crate) unsafe fn spawn_batch(
+        entities: Vec<Entity>,
+        components: Vec<ComponentInfo>,
+        columns: Vec<Vec<MaybeUninit<u8>>>,
+    ) -> Self {
+        Self { operation: Operation::SpawnBatch { entities, components, columns } }
     }
 
     pub(crate) fn despawn(entity: Entity) -> Self {
-        Self { jump: 1, operation: Operation::Despawn(entity) }
+        Self { operation: Operation::Despawn(entity) }
     }
 
     pub(crate) fn insert<C: Component>(val: C, entity: Entity) -> Self {
@@ -74,18 +139,45 @@ impl Command {
             std::slice::from_raw_parts((&raw const leaked).cast(), size_of::<C>()) //
         };
         // SAFETY: Safe because this is using static type info
-        unsafe { Self::insert_bytes(C::info(), bytes.into(), entity) }
+        unsafe { Self::insert_bytes(C::id().into(), C::info(), bytes.into(), entity) }
+    }
+
+    pub(crate) fn insert_pair<C: Component>(val: C, target: Entity, entity: Entity) -> Self {
+        let leaked = ManuallyDrop::new(val);
+        let bytes: &[MaybeUninit<u8>] = unsafe {
+            std::slice::from_raw_parts((&raw const leaked).cast(), size_of::<C>()) //
+        };
+        let field = FieldId::pair(C::id(), target);
+        // SAFETY: Safe because this is using static type info
+        unsafe { Self::insert_bytes(field, C::info(), bytes.into(), entity) }
     }
 
+    /// `info` is of the component or the relationship of a pair
     pub(crate) unsafe fn insert_bytes(
+        field: FieldId,
         info: ComponentInfo,
         bytes: Box<[MaybeUninit<u8>]>,
         entity: Entity,
     ) -> Self {
-        Self { jump: 1, operation: Operation::Insert { info, bytes, entity } }
+        Self { operation: Operation::Insert { field, info, bytes, entity } }
+    }
+
+    /// Component info is resolved when applied so components registered earlier in the same
+    /// flush can be used
+    pub(crate) unsafe fn insert_raw(
+        component: Entity,
+        bytes: Box<[MaybeUninit<u8>]>,
+        entity: Entity,
+    ) -> Self {
+        Self { operation: Operation::InsertRaw { component, bytes, entity } }
     }
 
     pub(crate) fn remove<Id: Into<FieldId>>(field: Id, entity: Entity) -> Self {
-        Self { jump: 1, operation: Operation::Remove { field: field.into(), entity } }
+        Self { operation: Operation::Remove { field: field.into(), entity } }
+    }
+
+    /// Remove every pair of a relationship, whatever its target
+    pub(crate) fn remove_relationship(relationship: Entity, entity: Entity) -> Self {
+        Self { operation: Operation::RemoveRelationship { relationship, entity } }
     }
 }
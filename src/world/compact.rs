@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use crate::{
+    component::{Component, ComponentInfo},
+    world::{
+        World,
+        archetype::{Archetype, ArchetypeId, Signature},
+        core::Core,
+        table::TableId,
+    },
+};
+
+impl World {
+    /// Flush & delete every archetype that has been empty for at least `flushes` flushes,
+    /// along with tables no archetype uses anymore.
+    pub fn compact(&self, flushes: usize) {
+        self.crust.flush_with(|core| core.compact(flushes));
+    }
+
+    /// Compact after every flush, deleting archetypes that have been empty for `flushes` flushes.
+    /// Disabled with `None`, which is the default.
+    pub fn set_auto_compact(&self, flushes: Option<usize>) {
+        self.crust.flush_with(|core| core.auto_compact = flushes);
+    }
+}
+
+impl Core {
+    /// Count flushes each archetype has been empty for & compact if enabled
+    pub(crate) fn end_flush(&mut self) {
+        for (_, archetype) in self.archetypes.iter_mut() {
+            archetype.empty_flushes = match archetype.entities.is_empty() {
+                true => archetype.empty_flushes.saturating_add(1),
+                false => 0,
+            };
+        }
+        if let Some(flushes) = self.auto_compact {
+            self.compact(flushes);
+        }
+    }
+
+    pub(crate) fn compact(&mut self, flushes: usize) {
+        let builtin = [
+            ArchetypeId::empty_archetype(),
+            self.signature_index[&Signature::new(&[ComponentInfo::id().into()])],
+        ];
+        let dead: Vec<_> = self
+            .archetypes
+            .iter()
+            .filter(|(id, archetype)| {
+                archetype.entities.is_empty()
+                    && archetype.empty_flushes >= flushes
+                    && !builtin.contains(id)
+            })
+            .map(|(id, _)| id)
+            .collect();
+        if dead.is_empty() {
+            return;
+        }
+
+        for id in dead {
+            let archetype = self.archetypes.remove(id).unwrap();
+            self.unlink(id, &archetype);
+        }
+
+        // Drop tables without archetypes
+        let used: HashSet<TableId> =
+            self.archetypes.iter().map(|(_, archetype)| archetype.table).collect();
+        let unused: Vec<_> = (self.tables.iter())
+            .filter(|(id, _)| !used.contains(id) && *id != TableId::empty_table())
+            .map(|(id, _)| id)
+            .collect();
+        for id in unused {
+            let table = self.tables.remove(id).unwrap();
+            self.table_index.remove(&table.signature);
+        }
+
+        self.archetype_generation += 1;
+    }
+
+    /// Remove all references to a deleted archetype
+    fn unlink(&mut self, id: ArchetypeId, archetype: &Archetype) {
+        self.signature_index.remove(&archetype.signature);
+        for field in archetype.signature.iter() {
+            if let Some(field_locations) = self.field_index.get_mut(field) {
+                field_locations.remove(&id);
+                if field_locations.is_empty() {
+                    self.field_index.remove(field);
+                }
+            }
+        }
+        for (field, edge) in archetype.edges.iter() {
+            for other in [edge.add, edge.remove].into_iter().flatten() {
+                let Some(other) = self.archetypes.get_mut(other) else {
+                    continue;
+                };
+                if let Some(other_edge) = other.edges.get_mut(field) {
+                    if other_edge.add == Some(id) {
+                        other_edge.add = None;
+                    }
+                    if other_edge.remove == Some(id) {
+                        other_edge.remove = None;
+                    }
+                    if other_edge.add.is_none() && other_edge.remove.is_none() {
+                        other.edges.remove(field);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as ssecs;
+    use crate::component::tests::*;
+    use ssecs_macros::*;
+
+    #[derive(Component)]
+    struct Chunk(#[allow(dead_code)] u32);
+
+    fn counts(world: &World) -> (usize, usize) {
+        world.crust.mantle(|mantle| {
+            (
+                mantle.core.archetypes.iter().count(),
+                mantle.core.tables.iter().count(),
+            )
+        })
+    }
+
+    #[test]
+    fn compact() {
+        let world = World::new();
+        let (archetypes, tables) = counts(&world);
+        let e = world.spawn().insert(Chunk(0)).insert(Player);
+        world.flush();
+        e.despawn();
+        world.flush();
+        assert_eq!((archetypes + 2, tables + 1), counts(&world));
+
+        // Not empty for long enough
+        world.compact(10);
+        assert_eq!((archetypes + 2, tables + 1), counts(&world));
+
+        world.compact(0);
+        assert_eq!((archetypes, tables), counts(&world));
+
+        // Removed archetypes are recreated on demand
+        let e = world.spawn().insert(Chunk(1)).insert(Player);
+        world.flush();
+        assert!(e.has(Player::id()));
+        e.remove(Chunk::id());
+        world.flush();
+        assert!(!e.has(Chunk::id()));
+    }
+
+    #[test]
+    fn auto_compact() {
+        let world = World::new();
+        world.set_auto_compact(Some(2));
+        let (archetypes, _) = counts(&world);
+        world.spawn().insert(Chunk(0)).despawn();
+        world.flush();
+        assert_eq!(archetypes + 1, counts(&world).0);
+        world.flush();
+        assert_eq!(archetypes, counts(&world).0);
+    }
+}
@@ -1,4 +1,11 @@
-use std::{collections::HashMap, mem::MaybeUninit};
+use std::{
+    collections::HashMap,
+    mem::MaybeUninit,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use derive_more::{Deref, DerefMut};
 use parking_lot::{
@@ -6,11 +13,18 @@ use parking_lot::{
 };
 
 use crate::{
-    component::{COMPONENT_ENTRIES, Component, ComponentInfo},
+    Error,
+    component::{COMPONENT_ENTRIES, Component, ComponentInfo, Storage},
     entity::Entity,
     slotmap::*,
-    world::archetype::{
-        Archetype, ArchetypeEdge, ArchetypeId, Column, ColumnIndex, FieldId, RowIndex, Signature,
+    world::{
+        archetype::{
+            Archetype, ArchetypeEdge, ArchetypeId, Column, ColumnIndex, FieldId, RowIndex,
+            Signature, Ticks,
+        },
+        removal::{Removal, RemovalLog},
+        sparse::SparseSet,
+        table::{GrowthPolicy, Table, TableId},
     },
 };
 
@@ -18,76 +32,124 @@ use crate::{
 pub(crate) struct EntityLocation {
     pub(crate) archetype: ArchetypeId,
     pub(crate) row: RowIndex,
+    pub(crate) table_row: RowIndex,
 }
 
 impl EntityLocation {
     pub(crate) fn uninitialized() -> Self {
-        Self { archetype: ArchetypeId::empty_archetype(), row: RowIndex(usize::MAX) }
+        Self {
+            archetype: ArchetypeId::empty_archetype(),
+            row: RowIndex(usize::MAX),
+            table_row: RowIndex(usize::MAX),
+        }
     }
 }
 
+/// Column of a field in each archetype's table. `None` for zero sized fields.
 #[derive(Deref, DerefMut, Default, Debug)]
-pub(crate) struct FieldLocations(HashMap<ArchetypeId, ColumnIndex>);
+pub(crate) struct FieldLocations(HashMap<ArchetypeId, Option<ColumnIndex>>);
 
 pub(crate) struct Core {
-    // Add read_index: SlotMap<Entity, EntityLocation> (a copy of entity_index) if this is too slow
-    entity_index: Mutex<SlotMap<Entity, EntityLocation>>,
-    field_index: HashMap<FieldId, FieldLocations>,
-    signature_index: HashMap<Signature, ArchetypeId>,
-    archetypes: SlotMap<ArchetypeId, Archetype>,
+    /// Only modified during flushes. Ids are reserved atomically & inserted on flush.
+    pub(crate) entity_index: SlotMap<Entity, EntityLocation>,
+    pub(crate) field_index: HashMap<FieldId, FieldLocations>,
+    pub(crate) signature_index: HashMap<Signature, ArchetypeId>,
+    pub(crate) archetypes: SlotMap<ArchetypeId, Archetype>,
+    pub(crate) table_index: HashMap<Signature, TableId>,
+    pub(crate) tables: SlotMap<TableId, Table>,
+    pub(crate) stable_index: HashMap<u64, Entity>,
+    pub(crate) sparse_sets: HashMap<FieldId, RwLock<SparseSet>>,
+    /// Changed when archetypes are created or deleted. Cached query matches are stale if it differs.
+    pub(crate) archetype_generation: u64,
+    /// See [`World::set_auto_compact`](crate::world::World::set_auto_compact)
+    pub(crate) auto_compact: Option<usize>,
+    /// Growth policy of new columns
+    pub(crate) growth: GrowthPolicy,
+    /// Stamped on rows when written. Advanced each time a query runs.
+    pub(crate) change_tick: AtomicU64,
+    /// Shared with [`RemovalReader`](crate::world::RemovalReader)s
+    pub(crate) removals: Arc<Mutex<RemovalLog>>,
 }
 
 impl Core {
     pub fn new() -> Self {
         // Add empty archetype & component info archetype
         let mut archetypes = SlotMap::<ArchetypeId, Archetype>::default();
+        let mut tables = SlotMap::<TableId, Table>::default();
         let mut entity_index = SlotMap::<Entity, EntityLocation>::default();
-        let empty_archetype_id = archetypes.insert(Archetype::default());
-        let component_info_archetype_id = archetypes.insert(Archetype::default());
+        let empty_table_id = tables.insert(Table::default());
+        let empty_archetype_id =
+            archetypes.insert(Archetype::new(Signature::default(), empty_table_id));
         assert_eq!(empty_archetype_id, ArchetypeId::empty_archetype());
-
-        if let Some(empty_archetype) = archetypes.get_mut(empty_archetype_id) {
-            // Make sure all component entities are sawned before init
-            // Needed if components add relationships (traits)
-            for n in 0..COMPONENT_ENTRIES.len() {
-                let id = entity_index
-                    .insert(EntityLocation { archetype: empty_archetype_id, row: RowIndex(n) });
-                empty_archetype.entities.push(id);
-            }
-            // Add ComponentInfo edge
-            let component_info_edge = &mut empty_archetype //
-                .edges
-                .entry(ComponentInfo::id().into())
-                .or_default();
-            component_info_edge.add = Some(component_info_archetype_id);
-        }
+        assert_eq!(empty_table_id, TableId::empty_table());
 
         // Mangually create ComponentInfo archetype
         let component_info_signature = Signature::new(&[ComponentInfo::id().into()]);
-        archetypes[component_info_archetype_id] = Archetype {
+        let component_info_table_id = tables.insert(Table {
             signature: component_info_signature.clone(),
             entities: Default::default(),
-            columns: vec![RwLock::new(Column::new(ComponentInfo::info()))],
-            edges: HashMap::from([(
-                ComponentInfo::id().into(),
-                ArchetypeEdge { remove: Some(empty_archetype_id), add: None },
-            )]),
-        };
+            columns: vec![RwLock::new(Column::new(
+                ComponentInfo::info(),
+                GrowthPolicy::default(),
+            ))],
+        });
+        let mut component_info_archetype =
+            Archetype::new(component_info_signature.clone(), component_info_table_id);
+        component_info_archetype.edges = HashMap::from([(
+            ComponentInfo::id().into(),
+            ArchetypeEdge { remove: Some(empty_archetype_id), add: None },
+        )]);
+        let component_info_archetype_id = archetypes.insert(component_info_archetype);
+
+        let empty_archetype = &mut archetypes[empty_archetype_id];
+        let empty_table = &mut tables[empty_table_id];
+        // Make sure all component entities are sawned before init
+        // Needed if components add relationships (traits)
+        for n in 0..COMPONENT_ENTRIES.len() {
+            let id = entity_index.insert(EntityLocation {
+                archetype: empty_archetype_id,
+                row: RowIndex(n),
+                table_row: RowIndex(n),
+            });
+            empty_archetype.entities.push(id);
+            empty_table.entities.push(id);
+        }
+        // Add ComponentInfo edge
+        let component_info_edge = &mut empty_archetype //
+            .edges
+            .entry(ComponentInfo::id().into())
+            .or_default();
+        component_info_edge.add = Some(component_info_archetype_id);
 
         Self {
             archetypes,
-            entity_index: Mutex::new(entity_index),
+            entity_index,
             field_index: HashMap::from([(
                 ComponentInfo::id().into(),
                 FieldLocations(HashMap::from([(
                     component_info_archetype_id,
-                    ColumnIndex(0),
+                    Some(ColumnIndex(0)),
                 )])),
             )]),
             signature_index: HashMap::from([
                 (Signature::default(), empty_archetype_id),
-                (component_info_signature, component_info_archetype_id),
+                (
+                    component_info_signature.clone(),
+                    component_info_archetype_id,
+                ),
             ]),
+            table_index: HashMap::from([
+                (Signature::default(), empty_table_id),
+                (component_info_signature, component_info_table_id),
+            ]),
+            tables,
+            stable_index: HashMap::new(),
+            sparse_sets: HashMap::new(),
+            change_tick: AtomicU64::new(0),
+            removals: Default::default(),
+            archetype_generation: 0,
+            auto_compact: None,
+            growth: GrowthPolicy::default(),
         }
     }
 
@@ -100,38 +162,52 @@ impl Core {
         if old_location.archetype == destination_id {
             return old_location;
         }
-        let entity_index = self.entity_index.get_mut();
+        let entity_index = &mut self.entity_index;
         let [old_archetype, new_archetype] = self //
             .archetypes
             .disjoint([old_location.archetype, destination_id])
             .unwrap();
 
         // Move entity entry from old archetype to new archetype
-        let entity = old_archetype.entities.swap_remove(*old_location.row);
-        new_archetype.entities.push(entity);
-
-        // Move bytes from old columns to new columns
-        old_archetype.signature.each_shared(&new_archetype.signature, |n, m| {
-            let old_column = old_archetype.columns[n].get_mut();
-            let new_column = new_archetype.columns[m].get_mut();
-            old_column.move_into(new_column, old_location.row);
-        });
-
-        // Update entity locations
-        let updated_location = EntityLocation {
+        let entity = old_archetype.entities[*old_location.row];
+        if let Some(moved) = old_archetype.swap_remove(old_location.row) {
+            entity_index[moved].row = old_location.row;
+        }
+        new_archetype.push(entity);
+        let mut updated_location = EntityLocation {
             archetype: destination_id,
             row: RowIndex(new_archetype.entities.len() - 1),
+            table_row: old_location.table_row,
         };
-        entity_index[entity] = updated_location;
-        if *old_location.row < old_archetype.entities.len() {
-            entity_index[old_archetype.entities[*old_location.row]].row = old_location.row;
-        }
 
-        // Drop any unmoved bytes
-        for column in old_archetype.columns.iter() {
-            column.write().shrink_to_fit(old_archetype.entities.len());
+        // Values stay in the same table if only tags changed
+        if old_archetype.table != new_archetype.table {
+            let [old_table, new_table] =
+                self.tables.disjoint([old_archetype.table, new_archetype.table]).unwrap();
+            old_table.entities.swap_remove(*old_location.table_row);
+            new_table.entities.push(entity);
+            updated_location.table_row = RowIndex(new_table.entities.len() - 1);
+
+            // Move bytes from old columns to new columns
+            let mut moved = vec![false; old_table.columns.len()];
+            old_table.signature.each_shared(&new_table.signature, |n, m| {
+                let old_column = old_table.columns[n].get_mut();
+                let new_column = new_table.columns[m].get_mut();
+                old_column.move_into(new_column, old_location.table_row);
+                moved[n] = true;
+            });
+
+            // Drop any unmoved bytes
+            for (column, _) in old_table.columns.iter_mut().zip(moved).filter(|(_, moved)| !moved) {
+                column.get_mut().swap_drop(old_location.table_row);
+            }
+
+            if let Some(moved) = old_table.entities.get(*old_location.table_row) {
+                entity_index[*moved].table_row = old_location.table_row;
+            }
         }
 
+        entity_index[entity] = updated_location;
         updated_location
     }
 
@@ -151,84 +227,246 @@ impl Core {
         }
     }
 
-    fn create_archetype(&mut self, signature: Signature) -> ArchetypeId {
+    fn create_table(&mut self, signature: Signature) -> TableId {
+        if let Some(id) = self.table_index.get(&signature) {
+            return *id;
+        }
+        let mut table = Table { signature: signature.clone(), ..Default::default() };
+        for field in signature.iter() {
+            let info = self.component_info(field.component()).unwrap();
+            table.columns.push(RwLock::new(Column::new(info, self.growth)));
+        }
+        let id = self.tables.insert(table);
+        self.table_index.insert(signature, id);
+        id
+    }
+
+    pub(crate) fn create_archetype(&mut self, signature: Signature) -> ArchetypeId {
         if let Some(id) = self.signature_index.get(&signature) {
             *id
         } else {
-            let mut new_archetype = Archetype {
-                signature: signature.clone(),
-                entities: Default::default(),
-                columns: Default::default(),
-                edges: Default::default(),
-            };
-
-            // Crate columns & add type meta
+            // Tags don't have columns
+            let mut table_signature = signature.clone();
             for field in signature.iter() {
-                // TODO: Check for pairs
-                let info = self.component_info(field.as_entity().unwrap()).unwrap();
-                new_archetype.columns.push(RwLock::new(Column::new(info)));
+                let info = self.component_info(field.component()).unwrap();
+                if info.size == 0 {
+                    table_signature = table_signature.without(*field);
+                }
             }
+            let table = self.create_table(table_signature);
 
             // Create new archetype with signature
-            let id = self.archetypes.insert(new_archetype);
+            let id = self.archetypes.insert(Archetype::new(signature.clone(), table));
             self.signature_index.insert(signature.clone(), id);
 
             // Populate field index with new archetype
-            for (n, field) in signature.iter().enumerate() {
-                self.field_index.entry(*field).or_default().insert(id, ColumnIndex(n));
+            let table_signature = &self.tables[table].signature;
+            for field in signature.iter() {
+                let column = table_signature.position(*field).map(ColumnIndex);
+                self.field_index.entry(*field).or_default().insert(id, column);
             }
 
             // Add missing edge connections
             self.connect_edges(signature, id);
+            self.archetype_generation += 1;
 
             id
         }
     }
 
-    pub(crate) fn entity_location(&mut self, entity: Entity) -> Option<EntityLocation> {
-        let entity_index = self.entity_index.get_mut();
-        entity_index.get(entity).copied()
+    /// Make room for `additional` entities with exactly `components`.
+    /// Returns the archetype the entities would be in.
+    pub(crate) fn reserve(
+        &mut self,
+        components: &[ComponentInfo],
+        additional: usize,
+    ) -> ArchetypeId {
+        let mut fields = Vec::new();
+        for info in components.iter().copied() {
+            match info.storage {
+                Storage::Table => fields.push(info.id.into()),
+                Storage::Sparse => {
+                    let growth = self.growth;
+                    (self.sparse_sets.entry(info.id.into()))
+                        .or_insert_with(|| RwLock::new(SparseSet::new(info, growth)))
+                        .get_mut()
+                        .reserve(additional);
+                }
+            }
+        }
+        let id = self.create_archetype(Signature::new(&fields));
+        let archetype = &mut self.archetypes[id];
+        archetype.entities.reserve(additional);
+        self.tables[archetype.table].reserve(additional);
+        id
+    }
+
+    /// Place uninitialized entities in one archetype.
+    /// `columns` holds the bytes of every entity for each component in `components`,
+    /// which must be distinct.
+    /// Entities that were despawned or initialized by an earlier command are skipped & their
+    /// values dropped, then the first of them is returned as `EntityNotFound`.
+    pub(crate) unsafe fn spawn_batch(
+        &mut self,
+        entities: &[Entity],
+        components: &[ComponentInfo],
+        columns: &[Vec<MaybeUninit<u8>>],
+    ) -> Result<(), Error> {
+        let placeable = |entity: &Entity| {
+            self.entity_index.get(*entity) == Some(&EntityLocation::uninitialized())
+        };
+        if let Some(skipped) = entities.iter().find(|entity| !placeable(entity)).copied() {
+            let mut placed = Vec::new();
+            let mut placed_columns = vec![Vec::new(); columns.len()];
+            for (n, entity) in entities.iter().enumerate() {
+                let place = placeable(entity);
+                if place {
+                    placed.push(*entity);
+                }
+                for ((info, bytes), placed_bytes) in
+                    components.iter().zip(columns).zip(&mut placed_columns)
+                {
+                    let value = &bytes[n * info.size..][..info.size];
+                    match place {
+                        true => placed_bytes.extend_from_slice(value),
+                        // SAFETY: Value is owned by the batch & never placed
+                        false => unsafe { info.drop_unaligned(value) },
+                    }
+                }
+            }
+            // SAFETY: Columns still hold a value for every placed entity
+            unsafe { self.spawn_batch(&placed, components, &placed_columns) }?;
+            return Err(Error::EntityNotFound(skipped));
+        }
+
+        let tick = self.tick();
+        let id = self.reserve(components, entities.len());
+        let entity_index = &mut self.entity_index;
+        let archetype = &mut self.archetypes[id];
+        let table = &mut self.tables[archetype.table];
+        for entity in entities.iter().copied() {
+            entity_index[entity] = EntityLocation {
+                archetype: id,
+                row: RowIndex(archetype.entities.len()),
+                table_row: RowIndex(table.entities.len()),
+            };
+            archetype.push(entity);
+            table.entities.push(entity);
+        }
+
+        for (info, bytes) in components.iter().zip(columns) {
+            if info.storage == Storage::Sparse {
+                let sparse_set = self.sparse_sets.get_mut(&info.id.into()).unwrap().get_mut();
+                for (n, entity) in entities.iter().enumerate() {
+                    // SAFETY: Bytes are of the sparse set's component type
+                    unsafe {
+                        sparse_set.insert(*entity, &bytes[n * info.size..][..info.size], tick)
+                    };
+                }
+            } else if let Some(column) = self.field_index[&info.id.into()][&id] {
+                // SAFETY: Bytes are whole rows of the column's component type
+                unsafe { table.columns[*column].get_mut().extend_from_bytes(bytes, tick) };
+            }
+        }
+        Ok(())
+    }
+
+    /// Free unused capacity of every column
+    pub(crate) fn shrink_to_fit(&mut self) {
+        for (_, archetype) in self.archetypes.iter_mut() {
+            archetype.entities.shrink_to_fit();
+        }
+        for (_, table) in self.tables.iter_mut() {
+            table.shrink_to_fit();
+        }
+        for sparse_set in self.sparse_sets.values_mut() {
+            sparse_set.get_mut().shrink_to_fit();
+        }
+    }
+
+    pub(crate) fn set_growth_policy(&mut self, growth: GrowthPolicy) {
+        self.growth = growth;
+        for (_, table) in self.tables.iter_mut() {
+            for column in &mut table.columns {
+                column.get_mut().growth = growth;
+            }
+        }
+        for sparse_set in self.sparse_sets.values_mut() {
+            sparse_set.get_mut().column.growth = growth;
+        }
     }
 
-    pub(crate) fn entity_location_locking(&self, entity: Entity) -> Option<EntityLocation> {
-        let entity_index = self.entity_index.lock();
-        entity_index.get(entity).copied()
+    /// Reserved entities that haven't been flushed are uninitialized
+    pub(crate) fn entity_location(&self, entity: Entity) -> Option<EntityLocation> {
+        match self.entity_index.get(entity) {
+            Some(location) => Some(*location),
+            None => self.entity_index.is_reserved(entity).then(EntityLocation::uninitialized),
+        }
     }
 
     fn get_component_info(
         entity_index: &SlotMap<Entity, EntityLocation>,
         field_index: &HashMap<FieldId, FieldLocations>,
         archetypes: &SlotMap<ArchetypeId, Archetype>,
+        tables: &SlotMap<TableId, Table>,
         component: Entity,
     ) -> Option<ComponentInfo> {
         field_index
             .get(&ComponentInfo::id().into())
             .zip(entity_index.get_ignore_generation(component))
             .and_then(|(field_locations, component_location)| {
-                let column = archetypes
-                    .get(component_location.archetype)?
+                let archetype = archetypes.get(component_location.archetype)?;
+                let column = tables[archetype.table]
                     .columns
-                    .get(**field_locations.get(&component_location.archetype)?)?
+                    .get(*(*field_locations.get(&component_location.archetype)?)?)?
                     .read();
-                let bytes = &column.get_chunk(component_location.row);
+                let bytes = &column.get_chunk(component_location.table_row);
                 let info = unsafe { std::ptr::read(bytes.as_ptr() as *const ComponentInfo) };
                 Some(info)
             })
     }
 
     /// Get metadata of a component
-    pub(crate) fn component_info(&mut self, component: Entity) -> Option<ComponentInfo> {
-        let entity_index = self.entity_index.get_mut();
+    pub(crate) fn component_info(&self, component: Entity) -> Option<ComponentInfo> {
+        let entity_index = &self.entity_index;
         let field_index = &self.field_index;
         let archetypes = &self.archetypes;
-        Self::get_component_info(entity_index, field_index, archetypes, component)
+        let tables = &self.tables;
+        Self::get_component_info(entity_index, field_index, archetypes, tables, component)
     }
 
-    pub(crate) fn component_info_locking(&self, component: Entity) -> Option<ComponentInfo> {
-        let entity_index = self.entity_index.lock();
-        let field_index = &self.field_index;
-        let archetypes = &self.archetypes;
-        Self::get_component_info(&entity_index, field_index, archetypes, component)
+    /// Metadata of every registered component
+    pub(crate) fn component_infos(&self) -> Vec<ComponentInfo> {
+        let Some(field_locations) = self.field_index.get(&ComponentInfo::id().into()) else {
+            return Vec::new();
+        };
+        let mut infos = Vec::new();
+        for (archetype, column) in field_locations.iter() {
+            let table = &self.tables[self.archetypes[*archetype].table];
+            let column = table.columns[*column.unwrap()].read();
+            for n in 0..column.no_chunks() {
+                let bytes = column.get_chunk(RowIndex(n));
+                infos.push(unsafe { std::ptr::read(bytes.as_ptr() as *const ComponentInfo) });
+            }
+        }
+        infos
+    }
+
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    pub(crate) fn archetypes(&self) -> impl Iterator<Item = &Archetype> {
+        self.archetypes.iter().map(|(_, archetype)| archetype)
+    }
+
+    /// If every component in the archetype is plain-old-data or zero sized
+    pub(crate) fn archetype_is_pod(&self, archetype: ArchetypeId) -> bool {
+        self.tables[self.archetypes[archetype].table].is_pod()
+    }
+
+    /// Get the column of a field in an archetype's table. `None` for tags.
+    pub(crate) fn column(&self, field: FieldId, archetype: ArchetypeId) -> Option<&RwLock<Column>> {
+        let column = (*self.field_index.get(&field)?.get(&archetype)?)?;
+        let table = self.archetypes.get(archetype)?.table;
+        self.tables[table].columns.get(*column)
     }
 
     pub(crate) fn archetype_has(&self, field: FieldId, archetype: ArchetypeId) -> bool {
@@ -237,91 +475,282 @@ impl Core {
             .is_some_and(|field_locations| field_locations.contains_key(&archetype))
     }
 
+    /// If an entity has a field in its archetype or in a sparse set
+    pub(crate) fn entity_has(
+        &self,
+        field: FieldId,
+        entity: Entity,
+        entity_location: EntityLocation,
+    ) -> bool {
+        match self.sparse_sets.get(&field) {
+            Some(sparse_set) => sparse_set.read().contains(entity),
+            None => self.archetype_has(field, entity_location.archetype),
+        }
+    }
+
     /// Get a component from an entity as type erased bytes
     pub(crate) fn get_bytes<'a>(
         &'a self,
         field: FieldId,
+        entity: Entity,
         entity_location: EntityLocation,
     ) -> Option<MappedRwLockReadGuard<'a, [MaybeUninit<u8>]>> {
-        self.field_index.get(&field).and_then(|field_locations| {
-            let column = self
-                .archetypes
-                .get(entity_location.archetype)?
-                .columns
-                .get(**field_locations.get(&entity_location.archetype)?)?
-                .read();
-            Some(RwLockReadGuard::map(column, |column| {
-                column.get_chunk(entity_location.row)
-            }))
-        })
+        if let Some(sparse_set) = self.sparse_sets.get(&field) {
+            let sparse_set = sparse_set.read();
+            let row = sparse_set.row(entity)?;
+            return Some(RwLockReadGuard::map(sparse_set, |sparse_set| {
+                sparse_set.column.get_chunk(row)
+            }));
+        }
+        let column = self.column(field, entity_location.archetype)?.read();
+        Some(RwLockReadGuard::map(column, |column| {
+            column.get_chunk(entity_location.table_row)
+        }))
+    }
+
+    /// Like [`Core::get_bytes`] but `Err` instead of blocking if the column is write locked
+    pub(crate) fn try_get_bytes<'a>(
+        &'a self,
+        field: FieldId,
+        entity: Entity,
+        entity_location: EntityLocation,
+    ) -> Result<Option<MappedRwLockReadGuard<'a, [MaybeUninit<u8>]>>, ()> {
+        if let Some(sparse_set) = self.sparse_sets.get(&field) {
+            let sparse_set = sparse_set.try_read().ok_or(())?;
+            let Some(row) = sparse_set.row(entity) else {
+                return Ok(None);
+            };
+            return Ok(Some(RwLockReadGuard::map(sparse_set, |sparse_set| {
+                sparse_set.column.get_chunk(row)
+            })));
+        }
+        let Some(column) = self.column(field, entity_location.archetype) else {
+            return Ok(None);
+        };
+        let column = column.try_read().ok_or(())?;
+        Ok(Some(RwLockReadGuard::map(column, |column| {
+            column.get_chunk(entity_location.table_row)
+        })))
     }
 
     /// Get a component from an entity as type erased bytes
     pub(crate) fn get_bytes_mut<'a>(
         &'a self,
         field: FieldId,
+        entity: Entity,
         entity_location: EntityLocation,
     ) -> Option<MappedRwLockWriteGuard<'a, [MaybeUninit<u8>]>> {
-        self.field_index.get(&field).and_then(|field_locations| {
-            let column = self
-                .archetypes
-                .get(entity_location.archetype)?
-                .columns
-                .get(**field_locations.get(&entity_location.archetype)?)?
-                .write();
-            Some(RwLockWriteGuard::map(column, |column| {
-                column.get_chunk_mut(entity_location.row)
-            }))
-        })
+        let tick = self.tick();
+        if let Some(sparse_set) = self.sparse_sets.get(&field) {
+            let sparse_set = sparse_set.write();
+            let row = sparse_set.row(entity)?;
+            return Some(RwLockWriteGuard::map(sparse_set, |sparse_set| {
+                sparse_set.column.mark_changed(row, tick);
+                sparse_set.column.get_chunk_mut(row)
+            }));
+        }
+        let column = self.column(field, entity_location.archetype)?.write();
+        Some(RwLockWriteGuard::map(column, |column| {
+            column.mark_changed(entity_location.table_row, tick);
+            column.get_chunk_mut(entity_location.table_row)
+        }))
+    }
+
+    /// Get a component from several entities as type erased pointers, write locking columns
+    /// shared by the entities once. Entities must be alive & distinct.
+    /// `None` if an entity doesn't have the field or it's zero sized.
+    pub(crate) fn get_many_bytes_mut<'a, const N: usize>(
+        &'a self,
+        field: FieldId,
+        entities: [Entity; N],
+    ) -> Option<(
+        Vec<MappedRwLockWriteGuard<'a, Column>>,
+        [*mut MaybeUninit<u8>; N],
+    )> {
+        let tick = self.tick();
+        let mut guards = Vec::new();
+        let mut rows = [(0, RowIndex(0)); N];
+        if let Some(sparse_set) = self.sparse_sets.get(&field) {
+            let sparse_set = sparse_set.write();
+            for (n, entity) in entities.iter().enumerate() {
+                rows[n] = (0, sparse_set.row(*entity)?);
+            }
+            guards.push(RwLockWriteGuard::map(sparse_set, |sparse_set| {
+                &mut sparse_set.column
+            }));
+        } else {
+            let mut columns = [None; N];
+            for (n, entity) in entities.iter().enumerate() {
+                let location = self.entity_location(*entity)?;
+                columns[n] = Some(self.column(field, location.archetype)?);
+                rows[n].1 = location.table_row;
+            }
+            // Lock in address order so concurrent calls can't deadlock each other
+            let mut locks: Vec<&RwLock<Column>> = columns.iter().flatten().copied().collect();
+            locks.sort_by_key(|lock| *lock as *const _);
+            locks.dedup_by(|a, b| std::ptr::eq(*a, *b));
+            for (n, column) in columns.iter().enumerate() {
+                rows[n].0 = locks.iter().position(|lock| std::ptr::eq(*lock, column.unwrap()))?;
+            }
+            guards.extend(locks.into_iter().map(|lock| RwLockWriteGuard::map(lock.write(), |c| c)));
+        }
+        let bytes = rows.map(|(column, row)| {
+            guards[column].mark_changed(row, tick);
+            guards[column].get_chunk_mut(row).as_mut_ptr()
+        });
+        Some((guards, bytes))
+    }
+
+    /// Change ticks of a component on an entity. `None` for zero sized components.
+    pub(crate) fn ticks(
+        &self,
+        field: FieldId,
+        entity: Entity,
+        entity_location: EntityLocation,
+    ) -> Option<Ticks> {
+        if let Some(sparse_set) = self.sparse_sets.get(&field) {
+            let sparse_set = sparse_set.read();
+            return sparse_set.column.ticks(sparse_set.row(entity)?);
+        }
+        self.column(field, entity_location.archetype)?.read().ticks(entity_location.table_row)
     }
 
+    pub(crate) fn tick(&self) -> u64 {
+        self.change_tick.load(Ordering::Relaxed)
+    }
+
+    /// Doesn't lock. The entity is added to the entity index on the next flush.
     pub(crate) fn create_uninitialized_entity(&self) -> Entity {
-        let mut entity_index = self.entity_index.lock();
-        entity_index.insert(EntityLocation::uninitialized())
+        self.entity_index.reserve()
+    }
+
+    pub(crate) fn create_uninitialized_entities(&self, n: usize) -> Vec<Entity> {
+        (0..n).map(|_| self.entity_index.reserve()).collect()
+    }
+
+    /// Add reserved entities to the entity index
+    pub(crate) fn flush_reserved(&mut self) {
+        self.entity_index.flush_reserved(EntityLocation::uninitialized);
+    }
+
+    /// Free the stable id of a component entity that's being despawned or unregistered
+    fn unregister_stable_id(&mut self, entity: Entity) {
+        let info = self.entity_location(entity).and(self.component_info(entity));
+        if let Some(info) = info
+            && self.stable_index.get(&info.stable_id) == Some(&entity)
+        {
+            self.stable_index.remove(&info.stable_id);
+        }
     }
 
     pub(crate) fn initialize_entity_location(&mut self, entity: Entity) -> EntityLocation {
-        let entity_index = self.entity_index.get_mut();
+        let entity_index = &mut self.entity_index;
         let mut location = entity_index[entity];
         if location == EntityLocation::uninitialized() {
             let empty_archetype = &mut self.archetypes[ArchetypeId::empty_archetype()];
             location.row = RowIndex(empty_archetype.entities.len());
-            empty_archetype.entities.push(entity);
+            empty_archetype.push(entity);
+            let empty_table = &mut self.tables[TableId::empty_table()];
+            location.table_row = RowIndex(empty_table.entities.len());
+            empty_table.entities.push(entity);
             entity_index[entity] = location;
         }
         location
     }
 
-    pub(crate) fn despawn(&mut self, entity: Entity) {
-        if let Some(location) = self.entity_index.get_mut().remove(entity) {
-            self.archetypes[location.archetype].drop(location.row);
+    /// Entities that don't exist are ignored
+    pub(crate) fn despawn(&mut self, entity: Entity) -> Result<(), Error> {
+        self.unregister_stable_id(entity);
+        let entity_index = &mut self.entity_index;
+        let Some(location) = entity_index.remove(entity) else {
+            return Ok(());
         };
+        if location != EntityLocation::uninitialized() {
+            let archetype = &mut self.archetypes[location.archetype];
+            if let Some(moved) = archetype.swap_remove(location.row) {
+                entity_index[moved].row = location.row;
+            }
+            if let Some(moved) = self.tables[archetype.table].swap_drop(location.table_row) {
+                entity_index[moved].table_row = location.table_row;
+            }
+        }
+        for sparse_set in self.sparse_sets.values_mut() {
+            sparse_set.get_mut().remove(entity);
+        }
+        self.removals.lock().push(Removal::Despawn(entity));
+        self.remove_pairs_targeting(entity)
     }
 
-    pub(crate) unsafe fn insert_bytes(
+    /// Remove pairs targeting an entity that no longer exists
+    pub(crate) fn remove_pairs_targeting(&mut self, entity: Entity) -> Result<(), Error> {
+        let index = Key::from(entity).index;
+        let pairs: Vec<_> = (self.field_index.keys().chain(self.sparse_sets.keys()))
+            .filter(|field| field.target_index() == Some(index))
+            .copied()
+            .collect();
+        for field in pairs {
+            let mut entities: Vec<_> = self.entities_with(field).collect();
+            if let Some(sparse_set) = self.sparse_sets.get_mut(&field) {
+                entities.extend(sparse_set.get_mut().entities.iter().copied());
+            }
+            for entity in entities {
+                self.try_remove_field(field, entity)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `info` is of the component or the relationship of a pair.
+    /// Nothing is changed if an error is returned.
+    pub(crate) unsafe fn try_insert_bytes(
         &mut self,
+        field: FieldId,
         info: ComponentInfo,
         bytes: &[MaybeUninit<u8>],
         entity: Entity,
-    ) -> EntityLocation {
-        assert_eq!(info.size, bytes.len());
-        let Some(current_location) = self.entity_location(entity) else {
-            panic!("Entity does not exist");
-        };
+    ) -> Result<EntityLocation, Error> {
+        if info.size != bytes.len() {
+            return Err(Error::SizeMismatch { expected: info.size, found: bytes.len() });
+        }
+        if self.entity_location(entity).is_none() {
+            return Err(Error::EntityNotFound(entity));
+        }
+        if field == ComponentInfo::id().into() {
+            // SAFETY: Bytes are a ComponentInfo
+            let registered =
+                unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const ComponentInfo) };
+            let previous = self.stable_index.get(&registered.stable_id);
+            if previous.is_some_and(|previous| *previous != entity) {
+                return Err(Error::StableIdTaken(registered.name));
+            }
+            self.stable_index.insert(registered.stable_id, entity);
+        }
+        // The spawn command of an entity reserved on another thread may not be applied yet
+        let current_location = self.initialize_entity_location(entity);
+        if info.storage == Storage::Sparse {
+            let (growth, tick) = (self.growth, self.tick());
+            let sparse_set = self
+                .sparse_sets
+                .entry(field)
+                .or_insert_with(|| RwLock::new(SparseSet::new(info, growth)));
+            // SAFETY: component info matches sparse set component info
+            unsafe { sparse_set.get_mut().insert(entity, bytes, tick) };
+            return Ok(current_location);
+        }
         let current_archetype = &self.archetypes[current_location.archetype];
         let entity = current_archetype.entities[*current_location.row];
 
         // Find destination archetype
-        let destination = if current_archetype.signature.contains(info.id.into()) {
+        let destination = if current_archetype.signature.contains(field) {
             current_location.archetype
         } else if let Some(edge) = current_archetype //
             .edges
-            .get(&info.id.into())
+            .get(&field)
             .and_then(|edge| edge.add)
         {
             edge
         } else {
-            self.create_archetype(current_archetype.signature.clone().with(info.id.into()))
+            self.create_archetype(current_archetype.signature.clone().with(field))
         };
 
         // SAFETY: New chunk is immediately created for entity
@@ -332,21 +761,42 @@ impl Core {
         //  - chunk corresponding to row if we moved to a new archetype is created
         //  - write_into will call drop fn on old component value if we didn't move archetype
         let updated_location = self.entity_location(entity).unwrap();
-        unsafe {
-            let column = self.field_index[&info.id.into()][&updated_location.archetype];
-            self.archetypes[destination] //
-                .columns[*column]
-                .get_mut()
-                .write_into(updated_location.row, bytes);
+        let tick = self.tick();
+        if let Some(column) = self.field_index[&field][&updated_location.archetype] {
+            let table = self.archetypes[destination].table;
+            unsafe {
+                self.tables[table].columns[*column].get_mut().write_into(
+                    updated_location.table_row,
+                    bytes,
+                    tick,
+                )
+            };
         }
-        updated_location
+        Ok(updated_location)
     }
 
-    pub(crate) fn remove_field(&mut self, field: FieldId, entity: Entity) -> EntityLocation {
-        let Some(current_location) = self.entity_location(entity) else {
-            panic!("Entity does not exist");
-        };
+    pub(crate) fn try_remove_field(
+        &mut self,
+        field: FieldId,
+        entity: Entity,
+    ) -> Result<EntityLocation, Error> {
+        let current_location = self.entity_location(entity).ok_or(Error::EntityNotFound(entity))?;
+        if let Some(sparse_set) = self.sparse_sets.get_mut(&field) {
+            if sparse_set.get_mut().remove(entity) {
+                self.removals.lock().push(Removal::Field { entity, field });
+            }
+            return Ok(current_location);
+        }
+        if current_location == EntityLocation::uninitialized() {
+            return Ok(current_location);
+        }
+        if field == ComponentInfo::id().into() {
+            self.unregister_stable_id(entity);
+        }
         let current_archetype = &self.archetypes[current_location.archetype];
+        if current_archetype.signature.contains(field) {
+            self.removals.lock().push(Removal::Field { entity, field });
+        }
 
         // Find destination
         let destination = if let Some(edge) = current_archetype //
@@ -360,6 +810,6 @@ impl Core {
         };
 
         // SAFETY: Should only ever drop components
-        unsafe { self.move_entity(current_location, destination) }
+        Ok(unsafe { self.move_entity(current_location, destination) })
     }
 }
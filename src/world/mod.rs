@@ -4,19 +4,34 @@ use std::{
         Arc,
         atomic::{AtomicUsize, Ordering},
     },
+    time::{Duration, Instant},
 };
 
+use parking_lot::{Condvar, Mutex, MutexGuard};
 use thread_local::ThreadLocal;
 
 use crate::{
-    component::{COMPONENT_ENTRIES, ComponentInfo},
+    Error,
+    component::{Bundle, COMPONENT_ENTRIES, ComponentInfo},
     entity::{Entity, View},
     query::QueryBuilder,
 };
 
 pub(crate) mod archetype;
 pub(crate) mod command;
+mod compact;
 pub(crate) mod core;
+mod removal;
+#[cfg(feature = "serde")]
+mod scene;
+mod snapshot;
+pub(crate) mod sparse;
+pub(crate) mod table;
+
+pub use archetype::FieldId;
+pub use removal::{Removal, RemovalReader};
+pub use snapshot::Snapshot;
+pub use table::GrowthPolicy;
 
 use command::Command;
 use core::Core;
@@ -27,7 +42,7 @@ pub struct World {
 
 pub(crate) struct Crust {
     pub(crate) mantle: UnsafeCell<Mantle>,
-    pub(crate) flush_guard: AtomicUsize, // nothing(0), flush(usize::MAX), blocked(1..usize::MAX)
+    pub(crate) flush_guard: FlushGuard,
 }
 
 unsafe impl Send for Crust {}
@@ -47,46 +62,192 @@ impl Mantle {
     }
 
     pub(crate) fn flush(&mut self) {
+        self.core.flush_reserved();
         for cell in self.commands.iter_mut() {
             for command in cell.get_mut().drain(..) {
                 command.apply(&mut self.core);
             }
         }
+        self.core.end_flush();
+    }
+
+    pub(crate) fn try_flush(&mut self) -> Result<(), Error> {
+        self.core.flush_reserved();
+        let mut first_err = None;
+        for cell in self.commands.iter_mut() {
+            for command in cell.get_mut().drain(..) {
+                if let Err(err) = command.try_apply(&mut self.core) {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+        self.core.end_flush();
+        first_err.map_or(Ok(()), Err)
+    }
+}
+
+/// Bit of the reader count set while a blocking flush waits for readers to finish
+const PENDING: usize = 1 << (usize::BITS - 2);
+
+pub(crate) struct FlushGuard {
+    state: AtomicUsize, // nothing(0), flush(usize::MAX), blocked(1..PENDING), pending(PENDING..)
+    /// If a blocking flush is pending or running
+    gate: Mutex<bool>,
+    readers_done: Condvar,
+    flush_done: Condvar,
+    /// Reads held by each thread. Nested reads pass a pending blocking flush, which would
+    /// otherwise wait on the outer read forever.
+    depth: ThreadLocal<Cell<usize>>,
+}
+
+impl FlushGuard {
+    fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            gate: Mutex::new(false),
+            readers_done: Condvar::new(),
+            flush_done: Condvar::new(),
+            depth: ThreadLocal::new(),
+        }
+    }
+}
+
+/// Ends a blocking flush when dropped so a panicking flush doesn't leave readers waiting
+struct BlockingFlush<'a>(&'a FlushGuard);
+
+impl Drop for BlockingFlush<'_> {
+    fn drop(&mut self) {
+        Crust::end_flush_blocking(self.0);
     }
 }
 
 #[allow(clippy::redundant_pattern_matching)]
 impl Crust {
-    pub(crate) fn begin_access(flush_guard: &AtomicUsize) {
-        if let Err(_) = flush_guard.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
-            (old < usize::MAX).then_some(old + 1)
+    /// Waits for a pending blocking flush to finish before reading
+    pub(crate) fn begin_access(flush_guard: &FlushGuard) {
+        loop {
+            let Err(err) = Self::try_begin_access(flush_guard) else {
+                return;
+            };
+            let mut pending = flush_guard.gate.lock();
+            if !*pending {
+                match Self::try_begin_access(flush_guard) {
+                    Ok(_) => return,
+                    Err(_) => panic!("{err}"),
+                }
+            }
+            while *pending {
+                flush_guard.flush_done.wait(&mut pending);
+            }
+        }
+    }
+
+    pub(crate) fn try_begin_access(flush_guard: &FlushGuard) -> Result<(), Error> {
+        let depth = flush_guard.depth.get_or_default();
+        let nested = depth.get() > 0;
+        match flush_guard.state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
+            let open = old < PENDING || nested;
+            (open && old & !PENDING < PENDING - 1).then_some(old + 1)
         }) {
-            panic!("Tried to read while structurally mutating");
+            Ok(_) => {
+                depth.set(depth.get() + 1);
+                Ok(())
+            }
+            Err(_) => Err(Error::FlushInProgress),
         }
     }
 
-    pub(crate) fn end_access(flush_guard: &AtomicUsize) {
-        if let Err(_) = flush_guard.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
-            (0 < old && old < usize::MAX).then_some(old - 1)
+    pub(crate) fn end_access(flush_guard: &FlushGuard) {
+        match flush_guard.state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
+            (old & !PENDING > 0 && old < usize::MAX).then_some(old - 1)
         }) {
-            panic!("No read to end");
+            Ok(old) if old - 1 == PENDING => {
+                let _gate = flush_guard.gate.lock();
+                flush_guard.readers_done.notify_all();
+            }
+            Ok(_) => {}
+            Err(_) => panic!("No read to end"),
+        }
+        let depth = flush_guard.depth.get_or_default();
+        depth.set(depth.get() - 1);
+    }
+
+    pub(crate) fn begin_flush(flush_guard: &FlushGuard) {
+        if let Err(err) = Self::try_begin_flush(flush_guard) {
+            panic!("{err}");
         }
     }
 
-    pub(crate) fn begin_flush(flush_guard: &AtomicUsize) {
-        if let Err(_) = flush_guard.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
+    pub(crate) fn try_begin_flush(flush_guard: &FlushGuard) -> Result<(), Error> {
+        match flush_guard.state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
             (0 == old).then_some(usize::MAX)
         }) {
-            panic!("Tried to structurally mutate while reading");
+            Ok(_) => Ok(()),
+            Err(old) if old & PENDING != 0 => Err(Error::FlushInProgress),
+            Err(_) => Err(Error::ReadersActive),
         }
     }
 
-    pub(crate) fn end_flush(flush_guard: &AtomicUsize) {
-        if let Err(_) = flush_guard.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
+    pub(crate) fn end_flush(flush_guard: &FlushGuard) {
+        if let Err(_) = flush_guard.state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
             (old == usize::MAX).then_some(0)
         }) {
             panic!("No write to end");
         }
+        // Wake a blocking flush waiting on a regular one
+        let _gate = flush_guard.gate.lock();
+        flush_guard.readers_done.notify_all();
+    }
+
+    /// Mark a flush as pending & wait for current readers to finish. New readers wait until the
+    /// flush is done. Returns [`Error::ReadersActive`] if readers are still active by `deadline`.
+    fn begin_flush_blocking(
+        flush_guard: &FlushGuard,
+        deadline: Option<Instant>,
+    ) -> Result<(), Error> {
+        let mut pending = flush_guard.gate.lock();
+        let timed_out = |pending: &mut MutexGuard<bool>, condvar: &Condvar| match deadline {
+            Some(deadline) => condvar.wait_until(pending, deadline).timed_out(),
+            None => {
+                condvar.wait(pending);
+                false
+            }
+        };
+        // One blocking flush at a time
+        while *pending {
+            if timed_out(&mut pending, &flush_guard.flush_done) {
+                return Err(Error::FlushInProgress);
+            }
+        }
+        *pending = true;
+        loop {
+            let acquired = flush_guard.state.fetch_update(
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                |old| match old {
+                    0 | PENDING => Some(usize::MAX),
+                    usize::MAX => None,
+                    readers => Some(readers | PENDING),
+                },
+            );
+            if let Ok(0 | PENDING) = acquired {
+                return Ok(());
+            }
+            if timed_out(&mut pending, &flush_guard.readers_done) {
+                let _ = flush_guard.state.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |old| {
+                    (old != usize::MAX).then_some(old & !PENDING)
+                });
+                *pending = false;
+                flush_guard.flush_done.notify_all();
+                return Err(Error::ReadersActive);
+            }
+        }
+    }
+
+    fn end_flush_blocking(flush_guard: &FlushGuard) {
+        Self::end_flush(flush_guard);
+        *flush_guard.gate.lock() = false;
+        flush_guard.flush_done.notify_all();
     }
 
     pub(crate) fn mantle<R>(&self, func: impl FnOnce(&Mantle) -> R) -> R {
@@ -96,10 +257,44 @@ impl Crust {
         ret
     }
 
+    pub(crate) fn try_mantle<R>(&self, func: impl FnOnce(&Mantle) -> R) -> Result<R, Error> {
+        Self::try_begin_access(&self.flush_guard)?;
+        let ret = func(unsafe { self.mantle.get().as_ref().unwrap() });
+        Self::end_access(&self.flush_guard);
+        Ok(ret)
+    }
+
     pub(crate) fn flush(&self) {
+        self.flush_with(|_| {});
+    }
+
+    /// Flush then run `func` with exclusive access to the world
+    pub(crate) fn flush_with<R>(&self, func: impl FnOnce(&mut Core) -> R) -> R {
         Self::begin_flush(&self.flush_guard);
-        unsafe { self.mantle.get().as_mut().unwrap().flush() };
+        let mantle = unsafe { self.mantle.get().as_mut().unwrap() };
+        mantle.flush();
+        let ret = func(&mut mantle.core);
         Self::end_flush(&self.flush_guard);
+        ret
+    }
+
+    /// Flush without panicking. Commands that fail are skipped & the first error is returned
+    /// once every other command has been applied.
+    pub(crate) fn try_flush(&self) -> Result<(), Error> {
+        Self::try_begin_flush(&self.flush_guard)?;
+        let mantle = unsafe { self.mantle.get().as_mut().unwrap() };
+        let ret = mantle.try_flush();
+        Self::end_flush(&self.flush_guard);
+        ret
+    }
+
+    pub(crate) fn flush_blocking(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        Self::begin_flush_blocking(&self.flush_guard, deadline)?;
+        let _end = BlockingFlush(&self.flush_guard);
+        let mantle = unsafe { self.mantle.get().as_mut().unwrap() };
+        mantle.flush();
+        Ok(())
     }
 }
 
@@ -108,7 +303,7 @@ impl World {
     pub fn new() -> Self {
         let mut world = Self {
             crust: Arc::new(Crust {
-                flush_guard: AtomicUsize::new(0),
+                flush_guard: FlushGuard::new(),
                 mantle: UnsafeCell::new(Mantle { core: Core::new(), commands: Default::default() }),
             }),
         };
@@ -126,9 +321,16 @@ impl World {
         self.get_entity(entity).unwrap()
     }
 
+    pub fn try_entity(&self, entity: Entity) -> Result<View<'_>, Error> {
+        self.crust
+            .try_mantle(|mantle| mantle.core.entity_location(entity))?
+            .map(|_| View { entity, world: self })
+            .ok_or(Error::EntityNotFound(entity))
+    }
+
     pub fn get_entity(&self, entity: Entity) -> Option<View<'_>> {
         self.crust.mantle(|mantle| {
-            mantle.core.entity_location_locking(entity).map(|_| View { entity, world: self })
+            mantle.core.entity_location(entity).map(|_| View { entity, world: self })
         })
     }
 
@@ -140,8 +342,82 @@ impl World {
         })
     }
 
+    /// Spawn an entity for each bundle. All entities are placed in the same archetype with one
+    /// command, which is applied on the next flush.
+    /// Panics if the bundle contains the same component more than once.
+    pub fn spawn_batch<B: Bundle>(&self, bundles: impl IntoIterator<Item = B>) -> Vec<Entity> {
+        let mut components = Vec::new();
+        B::infos(&mut components);
+        let mut ids: Vec<FieldId> = components.iter().map(|info| info.id.into()).collect();
+        ids.sort();
+        if ids.windows(2).any(|pair| pair[0] == pair[1]) {
+            panic!("Bundle contains duplicate components");
+        }
+        let bundles = bundles.into_iter();
+        let mut columns: Vec<_> = (components.iter())
+            .map(|info| Vec::with_capacity(info.size * bundles.size_hint().0))
+            .collect();
+        let mut len = 0;
+        for bundle in bundles {
+            let mut n = 0;
+            bundle.take(&mut |bytes| {
+                columns[n].extend_from_slice(bytes);
+                n += 1;
+            });
+            len += 1;
+        }
+        self.crust.mantle(|mantle| {
+            let entities = mantle.core.create_uninitialized_entities(len);
+            // SAFETY: Every bundle wrote one value for each of its components
+            let command = unsafe { Command::spawn_batch(entities.clone(), components, columns) };
+            mantle.enqueue(command);
+            entities
+        })
+    }
+
+    /// Flush & register a component at runtime from its type erased description.
+    /// The `id` of `info` is replaced with the new component entity.
+    /// Panics if another component is registered with the same stable id.
+    pub fn register_component(&self, mut info: ComponentInfo) -> Entity {
+        let registered = self.crust.flush_with(|core| {
+            if core.stable_index.contains_key(&info.stable_id) {
+                return Err(Error::StableIdTaken(info.name));
+            }
+            // Spawned & inserted directly so it's registered within this flush
+            let component = core.create_uninitialized_entity();
+            core.flush_reserved();
+            core.initialize_entity_location(component);
+            info.id = component;
+            Command::insert(info, component).try_apply(core)?;
+            Ok(component)
+        });
+        registered.unwrap_or_else(|err| panic!("{err}"))
+    }
+
     pub fn component_info(&self, component: Entity) -> Option<ComponentInfo> {
-        self.crust.mantle(|mantle| mantle.core.component_info_locking(component))
+        self.crust.mantle(|mantle| mantle.core.component_info(component))
+    }
+
+    /// Find the component registered with a stable id. See [`ComponentInfo::stable_id`].
+    pub fn component_by_stable_id(&self, stable_id: u64) -> Option<Entity> {
+        self.crust.mantle(|mantle| mantle.core.stable_index.get(&stable_id).copied())
+    }
+
+    /// Flush & make room for `additional` entities with exactly the components in `B`
+    pub fn reserve<B: Bundle>(&self, additional: usize) {
+        let mut components = Vec::new();
+        B::infos(&mut components);
+        self.crust.flush_with(|core| core.reserve(&components, additional));
+    }
+
+    /// Flush & set how columns grow when they run out of room. Applies to existing columns.
+    pub fn set_growth_policy(&self, growth: GrowthPolicy) {
+        self.crust.flush_with(|core| core.set_growth_policy(growth));
+    }
+
+    /// Flush & free unused capacity of every column
+    pub fn shrink_to_fit(&self) {
+        self.crust.flush_with(|core| core.shrink_to_fit());
     }
 
     pub fn query(&self) -> QueryBuilder {
@@ -154,6 +430,21 @@ impl World {
     pub fn flush(&self) {
         self.crust.flush();
     }
+
+    /// Like [`World::flush`] but returns an error instead of panicking. Commands that fail
+    /// (e.g. inserting on a despawned entity) are skipped, their values are dropped & the first
+    /// error is returned after the rest of the flush is applied.
+    /// Values of unregistered components or of the wrong size can't be dropped & are leaked.
+    pub fn try_flush(&self) -> Result<(), Error> {
+        self.crust.try_flush()
+    }
+
+    /// Flush once every reader on other threads is done instead of panicking. New readers wait
+    /// until the flush is done. Returns [`Error::ReadersActive`] if `timeout` runs out first.
+    /// Waits forever without a timeout, so never call it while holding a column guard.
+    pub fn flush_blocking(&self, timeout: Option<Duration>) -> Result<(), Error> {
+        self.crust.flush_blocking(timeout)
+    }
 }
 
 #[cfg(test)]
@@ -162,6 +453,7 @@ mod tests {
     use super::*;
     use crate as ssecs;
     use crate::component::{Component, tests::*};
+    use archetype::Signature;
     use ssecs_macros::*;
     use std::sync::Arc;
 
@@ -193,6 +485,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn stable_ids() {
+        let world = World::new();
+        assert_eq!(
+            world.component_by_stable_id(Foo::info().stable_id),
+            Some(Foo::id())
+        );
+        assert_eq!(
+            world.component_by_stable_id(crate::component::stable_id("Missing")),
+            None
+        );
+
+        let dynamic = world.register_component(ComponentInfo::new("Dynamic", 1, 1));
+        let stable_id = crate::component::stable_id("Dynamic");
+        assert_eq!(world.component_by_stable_id(stable_id), Some(dynamic));
+
+        world.entity(dynamic).despawn();
+        world.flush();
+        assert_eq!(world.component_by_stable_id(stable_id), None);
+        let again = world.register_component(ComponentInfo::new("Dynamic", 1, 1));
+        assert_eq!(world.component_by_stable_id(stable_id), Some(again));
+    }
+
+    #[test]
+    #[should_panic]
+    fn stable_id_taken() {
+        let world = World::new();
+        world.register_component(ComponentInfo::new("Taken", 1, 1));
+        world.register_component(ComponentInfo::new("Taken", 2, 1));
+    }
+
     #[test]
     fn zsts() {
         let world = World::new();
@@ -206,6 +529,167 @@ mod tests {
         assert_eq!(false, world.entity(e).has(Player::id()));
     }
 
+    #[test]
+    fn tags_share_table() {
+        let world = World::new();
+        let a = world.spawn().insert(Foo(0)).insert(Bar(1));
+        let b = world.spawn().insert(Foo(2)).insert(Bar(3));
+        world.flush();
+        let location =
+            |e: View| world.crust.mantle(|mantle| mantle.core.entity_location(e.id()).unwrap());
+        let table = |e: View| {
+            world.crust.mantle(|mantle| mantle.core.archetypes[location(e).archetype].table)
+        };
+        let before = location(a);
+
+        a.insert(Player);
+        world.flush();
+        assert_ne!(before.archetype, location(a).archetype);
+        assert_eq!(table(b), table(a));
+        assert_eq!(before.table_row, location(a).table_row);
+        assert_eq!(0, a.get::<Foo>().unwrap().0);
+
+        // Values of other entities in the table are untouched
+        a.remove(Foo::id());
+        world.flush();
+        assert_eq!(1, a.get::<Bar>().unwrap().0);
+        assert_eq!(2, b.get::<Foo>().unwrap().0);
+        assert_eq!(3, b.get::<Bar>().unwrap().0);
+        assert_eq!(true, a.has(Player::id()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn growth_factor() {
+        GrowthPolicy::new(1.0, 4);
+    }
+
+    #[test]
+    fn reserve() {
+        let world = World::new();
+        world.set_growth_policy(GrowthPolicy::new(1.5, 4));
+        world.reserve::<(Foo, Bar, Player)>(100);
+        let capacities = || {
+            world.crust.mantle(|mantle| {
+                let core = &mantle.core;
+                let id = core.signature_index
+                    [&Signature::new(&[Foo::id().into(), Bar::id().into(), Player::id().into()])];
+                let table = &core.tables[core.archetypes[id].table];
+                table.columns.iter().map(|column| column.read().capacity()).collect::<Vec<_>>()
+            })
+        };
+        let at_least = |rows| capacities().iter().all(|capacity| *capacity >= rows);
+        assert!(at_least(100));
+
+        for n in 0..100 {
+            world.spawn().insert(Foo(n)).insert(Bar(n)).insert(Player);
+        }
+        world.flush();
+        assert!(at_least(100));
+
+        world.spawn().insert(Foo(0)).insert(Bar(0)).insert(Player);
+        world.flush();
+        assert!(at_least(150));
+
+        world.shrink_to_fit();
+        assert!(at_least(101));
+    }
+
+    #[test]
+    fn spawn_batch() {
+        let val = Arc::new(0_u8);
+        let world = World::new();
+        world.spawn().insert(Foo(0)).insert(Bar(0)).insert(Player);
+        let entities = world.spawn_batch((0..10).map(|n| {
+            (
+                Foo(n),
+                (Bar(n + 1), Player),
+                Sparse(n + 2),
+                RefCounted(val.clone()),
+            )
+        }));
+        assert_eq!(11, Arc::strong_count(&val));
+        world.flush();
+
+        let locations: Vec<_> = world.crust.mantle(|mantle| {
+            (entities.iter()).map(|entity| mantle.core.entity_location(*entity).unwrap()).collect()
+        });
+        for (n, (entity, location)) in entities.iter().zip(&locations).enumerate() {
+            let e = world.entity(*entity);
+            assert_eq!(n as u8, e.get::<Foo>().unwrap().0);
+            assert_eq!(n as u8 + 1, e.get::<Bar>().unwrap().0);
+            assert_eq!(n as u8 + 2, e.get::<Sparse>().unwrap().0);
+            assert_eq!(true, e.has(Player::id()));
+            assert_eq!(locations[0].archetype, location.archetype);
+            assert_eq!(n, *location.table_row);
+        }
+
+        for entity in entities {
+            world.entity(entity).despawn();
+        }
+        world.flush();
+        assert_eq!(1, Arc::strong_count(&val));
+    }
+
+    #[test]
+    fn spawn_batch_duplicates() {
+        let val = Arc::new(0_u8);
+        let world = World::new();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            world.spawn_batch([(RefCounted(val.clone()), Foo(0), Foo(1))]);
+        }));
+        assert!(result.is_err());
+        // Nothing was enqueued & the bundle was dropped
+        world.flush();
+        assert_eq!(1, Arc::strong_count(&val));
+    }
+
+    #[test]
+    fn column_rows() {
+        let world = World::new();
+        let entities: Vec<_> = (0..3).map(|n| world.spawn().insert(Foo(n)).id()).collect();
+        world.flush();
+
+        // Overwrite a row that isn't the last one
+        world.entity(entities[0]).insert(Foo(7));
+        world.flush();
+        assert_eq!(7, world.entity(entities[0]).get::<Foo>().unwrap().0);
+        assert_eq!(1, world.entity(entities[1]).get::<Foo>().unwrap().0);
+
+        // Swap the last row into the first
+        world.entity(entities[0]).despawn();
+        world.flush();
+        assert_eq!(1, world.entity(entities[1]).get::<Foo>().unwrap().0);
+        assert_eq!(2, world.entity(entities[2]).get::<Foo>().unwrap().0);
+    }
+
+    #[test]
+    fn parallel_spawn() {
+        let world = World::new();
+        let free = world.spawn().id();
+        world.entity(free).despawn();
+        world.flush();
+
+        let entities: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| (0..100).map(|_| world.spawn().id()).collect::<Vec<_>>()))
+                .collect();
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        });
+        // Reserved entities exist before the flush
+        assert_eq!(true, world.get_entity(entities[0]).is_some());
+        assert_eq!(false, world.entity(entities[0]).has(Foo::id()));
+
+        world.flush();
+        let unique: std::collections::HashSet<_> = entities.iter().collect();
+        assert_eq!(400, unique.len());
+        assert_eq!(false, unique.contains(&free));
+        for entity in entities {
+            world.entity(entity).insert(Foo(1));
+        }
+        world.flush();
+    }
+
     #[test]
     fn set_remove() {
         let world = World::new();
@@ -230,6 +714,246 @@ mod tests {
         assert_eq!(1, e.get::<Bar>().unwrap().0);
     }
 
+    #[test]
+    fn runtime_component() {
+        let world = World::new();
+        let dynamic = world.register_component(ComponentInfo::new("Dynamic", 4, 4));
+        let bytes = 7_u32.to_ne_bytes().map(std::mem::MaybeUninit::new);
+        let e = unsafe { world.spawn().insert_raw(dynamic, &bytes) };
+        world.flush();
+
+        assert_eq!(
+            world.component_info(dynamic).map(|info| info.name),
+            Some("Dynamic")
+        );
+        assert_eq!(true, e.has(dynamic));
+        let raw: [u8; 4] = std::array::from_fn(|n| unsafe {
+            e.get_raw(dynamic).unwrap()[n].assume_init() //
+        });
+        assert_eq!(7, u32::from_ne_bytes(raw));
+
+        e.get_raw_mut(dynamic).unwrap()[0] = std::mem::MaybeUninit::new(8);
+        assert_eq!(8, unsafe { e.get_raw(dynamic).unwrap()[0].assume_init() });
+
+        e.remove(dynamic);
+        world.flush();
+        assert_eq!(false, e.has(dynamic));
+    }
+
+    #[derive(Component)]
+    #[component(storage = "sparse")]
+    struct Sparse(u8);
+
+    #[derive(Component)]
+    #[component(storage = "sparse")]
+    struct SparseTag;
+
+    #[test]
+    fn sparse() {
+        let world = World::new();
+        let e = world.spawn().insert(Foo(0));
+        world.flush();
+        let table_location = world.crust.mantle(|mantle| mantle.core.entity_location(e.id()));
+
+        e.insert(Sparse(1)).insert(SparseTag);
+        world.flush();
+        assert_eq!(true, e.has(Sparse::id()));
+        assert_eq!(true, e.has(SparseTag::id()));
+        assert_eq!(1, e.get::<Sparse>().unwrap().0);
+        e.get_mut::<Sparse>().unwrap().0 = 2;
+        assert_eq!(2, e.get::<Sparse>().unwrap().0);
+        // Archetype is unchanged
+        let location = world.crust.mantle(|mantle| mantle.core.entity_location(e.id()));
+        assert_eq!(table_location, location);
+
+        let other = world.spawn().insert(Sparse(5));
+        e.remove(SparseTag::id());
+        world.flush();
+        assert_eq!(false, e.has(SparseTag::id()));
+        assert_eq!(2, e.get::<Sparse>().unwrap().0);
+
+        e.despawn();
+        world.flush();
+        assert_eq!(5, other.get::<Sparse>().unwrap().0);
+    }
+
+    #[derive(Component, Debug)]
+    struct Position(i32);
+
+    #[test]
+    fn debug_view() {
+        use crate::hierarchy::ChildOf;
+
+        let world = World::new();
+        let parent = world.spawn().named("parent");
+        let e = world.spawn().named("child").insert(Position(3)).insert(Foo(0)).insert(Player);
+        e.child_of(parent.id()).insert(Sparse(1));
+        world.flush();
+        let debug = format!("{e:?}");
+        assert!(debug.starts_with(&format!("View {{ id: {}, name: \"child\"", e.id())));
+        assert!(debug.contains("Position(3)"));
+        assert!(debug.contains(Foo::info().name));
+        assert!(debug.contains(Player::info().name));
+        assert!(debug.contains(Sparse::info().name));
+        assert!(debug.contains(&format!("({}, {})", ChildOf::info().name, parent.id())));
+        assert!(!debug.contains("Name("));
+
+        // Write locked values aren't waited on
+        let position = e.get_mut::<Position>().unwrap();
+        let debug = format!("{e:?}");
+        assert!(debug.contains(&format!("{} <borrowed>", Position::info().name)));
+        std::mem::drop(position);
+
+        assert_eq!("null", format!("{:?}", Entity::null()));
+        assert_eq!(format!("{}", e.id()), format!("{:?}", e.id()));
+    }
+
+    #[test]
+    fn sparse_tag_swap_drop() {
+        let world = World::new();
+        let a = world.spawn().insert(SparseTag);
+        let b = world.spawn().insert(SparseTag);
+        world.flush();
+
+        a.remove(SparseTag::id());
+        world.flush();
+        assert_eq!(false, a.has(SparseTag::id()));
+        assert_eq!(true, b.has(SparseTag::id()));
+    }
+
+    #[test]
+    fn fallible() {
+        let world = World::new();
+        let e = world.spawn().insert(Foo(1));
+        world.flush();
+        assert_eq!(1, e.try_get::<Foo>().unwrap().unwrap().0);
+        assert!(e.try_get::<Bar>().unwrap().is_none());
+
+        // Readers block flushing
+        let guard = e.get::<Foo>().unwrap();
+        assert_eq!(Err(Error::ReadersActive), world.try_flush());
+        std::mem::drop(guard);
+
+        e.despawn();
+        world.flush();
+        assert_eq!(
+            Err(Error::EntityNotFound(e.id())),
+            world.try_entity(e.id()).map(|e| e.id())
+        );
+        assert_eq!(
+            Err(Error::EntityNotFound(e.id())),
+            e.try_get::<Foo>().map(|_| ())
+        );
+
+        // Failed commands are skipped without stopping the flush
+        let other = world.spawn();
+        e.insert(Bar(0));
+        other.insert(Bar(2));
+        let unregistered = world.spawn().id();
+        unsafe { other.insert_raw(unregistered, &[]) };
+        assert_eq!(Err(Error::EntityNotFound(e.id())), world.try_flush());
+        assert_eq!(2, other.get::<Bar>().unwrap().0);
+        assert_eq!(Ok(()), world.try_flush());
+
+        // Values of failed inserts are dropped
+        let val = Arc::new(0_u8);
+        e.insert(RefCounted(val.clone()));
+        assert_eq!(Err(Error::EntityNotFound(e.id())), world.try_flush());
+        assert_eq!(1, Arc::strong_count(&val));
+    }
+
+    #[test]
+    fn apply_out_of_order() {
+        use crate::component::Bundle;
+
+        // Commands from another thread's queue can be applied before an entity's spawn command
+        let world = World::new();
+        let e = world.crust.flush_with(|core| {
+            let e = core.create_uninitialized_entity();
+            core.flush_reserved();
+            Command::insert(Foo(3), e).try_apply(core).unwrap();
+            e
+        });
+        assert_eq!(3, world.entity(e).get::<Foo>().unwrap().0);
+
+        // Batched entities that are gone are skipped & their values dropped
+        let val = Arc::new(0_u8);
+        let mut columns = vec![Vec::new()];
+        for _ in 0..2 {
+            RefCounted(val.clone()).take(&mut |bytes| columns[0].extend_from_slice(bytes));
+        }
+        let (entities, result) = world.crust.flush_with(|core| {
+            let entities = core.create_uninitialized_entities(2);
+            core.flush_reserved();
+            core.despawn(entities[0]).unwrap();
+            let command = unsafe {
+                Command::spawn_batch(entities.clone(), vec![RefCounted::info()], columns)
+            };
+            (entities, command.try_apply(core))
+        });
+        assert_eq!(Err(Error::EntityNotFound(entities[0])), result);
+        assert_eq!(2, Arc::strong_count(&val));
+        assert_eq!(true, world.entity(entities[1]).has(RefCounted::id()));
+    }
+
+    #[test]
+    fn flush_blocking() {
+        let world = World::new();
+        let e = world.spawn().insert(Foo(1));
+        world.flush();
+
+        let guard = e.get::<Foo>().unwrap();
+        let timeout = Some(Duration::from_millis(10));
+        assert_eq!(Err(Error::ReadersActive), world.flush_blocking(timeout));
+        // Readers aren't blocked after a timeout
+        assert_eq!(1, e.get::<Foo>().unwrap().0);
+
+        e.insert(Bar(2));
+        std::thread::scope(|scope| {
+            let flush = scope.spawn(|| world.flush_blocking(None));
+            std::thread::sleep(Duration::from_millis(10));
+            std::mem::drop(guard);
+            flush.join().unwrap()
+        })
+        .unwrap();
+        assert_eq!(2, e.get::<Bar>().unwrap().0);
+
+        // A thread that already reads can keep reading while a flush waits on it
+        let guard = e.get::<Foo>().unwrap();
+        e.insert(Foo(3));
+        std::thread::scope(|scope| {
+            let flush = scope.spawn(|| world.flush_blocking(None));
+            let state = &world.crust.flush_guard.state;
+            while state.load(Ordering::SeqCst) & PENDING == 0 {
+                std::thread::yield_now();
+            }
+            assert_eq!(2, e.get::<Bar>().unwrap().0);
+            assert!(format!("{e:?}").contains(Bar::info().name));
+            std::mem::drop(guard);
+            flush.join().unwrap()
+        })
+        .unwrap();
+        assert_eq!(3, e.get::<Foo>().unwrap().0);
+    }
+
+    #[test]
+    fn flush_blocking_panic() {
+        let world = World::new();
+        let e = world.spawn().insert(Foo(1));
+        world.flush();
+        e.despawn();
+        e.insert(Bar(0));
+        let flush =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| world.flush_blocking(None)));
+        assert!(flush.is_err());
+
+        // Neither readers nor flushes are left waiting
+        let other = world.spawn().insert(Foo(2));
+        let timeout = Some(Duration::from_millis(10));
+        assert_eq!(Ok(()), world.flush_blocking(timeout));
+        assert_eq!(2, other.get::<Foo>().unwrap().0);
+    }
+
     #[test]
     fn despawn() {
         let world = World::new();
@@ -242,6 +966,18 @@ mod tests {
         assert!(world.get_entity(e).is_none());
     }
 
+    #[test]
+    fn despawn_with_tag() {
+        let world = World::new();
+        let e = world.spawn().insert(Player).id();
+        world.flush();
+        assert!(world.get_entity(e).is_some());
+
+        world.entity(e).despawn();
+        world.flush();
+        assert!(world.get_entity(e).is_none());
+    }
+
     #[test]
     fn drop() {
         let val = Arc::new(0_u8);
@@ -0,0 +1,139 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    entity::Entity,
+    world::{World, archetype::FieldId},
+};
+
+/// A component or pair removed from an entity, or a despawned entity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Removal {
+    Field { entity: Entity, field: FieldId },
+    Despawn(Entity),
+}
+
+/// Removals since each reader last read. Only recorded while a reader exists.
+#[derive(Debug, Default)]
+pub(crate) struct RemovalLog {
+    entries: VecDeque<Removal>,
+    /// Position of the first entry since the log was created
+    start: u64,
+    /// Position each reader reads from next
+    cursors: HashMap<u64, u64>,
+    next_reader: u64,
+}
+
+impl RemovalLog {
+    pub fn push(&mut self, removal: Removal) {
+        if !self.cursors.is_empty() {
+            self.entries.push_back(removal);
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.start + self.entries.len() as u64
+    }
+
+    fn register(&mut self) -> u64 {
+        let reader = self.next_reader;
+        self.next_reader += 1;
+        self.cursors.insert(reader, self.end());
+        reader
+    }
+
+    fn unregister(&mut self, reader: u64) {
+        self.cursors.remove(&reader);
+        self.trim();
+    }
+
+    fn read(&mut self, reader: u64) -> Vec<Removal> {
+        let end = self.end();
+        let cursor = self.cursors.insert(reader, end).unwrap();
+        let out = self.entries.range((cursor - self.start) as usize..).copied().collect();
+        self.trim();
+        out
+    }
+
+    /// Drop entries every reader has read
+    fn trim(&mut self) {
+        let read = self.cursors.values().copied().min().unwrap_or(self.end());
+        self.entries.drain(..(read - self.start) as usize);
+        self.start = read;
+    }
+}
+
+/// Reads removals & despawns made since it was created or last read.
+/// Every reader has its own cursor so they don't consume each other's removals.
+pub struct RemovalReader {
+    log: Arc<Mutex<RemovalLog>>,
+    reader: u64,
+}
+
+impl RemovalReader {
+    /// Removals applied by flushes since the last read, in the order they were applied
+    pub fn read(&mut self) -> Vec<Removal> {
+        self.log.lock().read(self.reader)
+    }
+}
+
+impl Drop for RemovalReader {
+    fn drop(&mut self) {
+        self.log.lock().unregister(self.reader);
+    }
+}
+
+impl World {
+    /// Start reading removals & despawns. Removals are only recorded while a reader exists.
+    /// Entries are kept until every reader has read them, so a reader that is never read
+    /// makes the log grow without bound. Drop readers that are no longer read.
+    pub fn removal_reader(&self) -> RemovalReader {
+        let log = self.crust.mantle(|mantle| mantle.core.removals.clone());
+        let reader = log.lock().register();
+        RemovalReader { log, reader }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{Component, tests::*};
+
+    #[test]
+    fn removal_log() {
+        let world = World::new();
+        let a = world.spawn().insert(Player).insert(Health);
+        let b = world.spawn().insert(Player);
+        world.flush();
+
+        let mut first = world.removal_reader();
+        a.remove(Health::id());
+        // Not removed since it was never inserted
+        b.remove(Health::id());
+        world.flush();
+
+        let mut second = world.removal_reader();
+        b.despawn();
+        world.flush();
+
+        let health = Removal::Field { entity: a.id(), field: Health::id().into() };
+        assert_eq!(vec![health, Removal::Despawn(b.id())], first.read());
+        assert_eq!(vec![Removal::Despawn(b.id())], second.read());
+        assert!(first.read().is_empty());
+
+        // Entries are dropped once every reader has read them
+        a.despawn();
+        world.flush();
+        drop(second);
+        assert_eq!(
+            1,
+            world.crust.mantle(|mantle| mantle.core.removals.lock().entries.len())
+        );
+        assert_eq!(vec![Removal::Despawn(a.id())], first.read());
+        assert!(world.crust.mantle(|mantle| mantle.core.removals.lock().entries.is_empty()));
+    }
+}
@@ -0,0 +1,572 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    io::{Read, Write},
+    mem::MaybeUninit,
+};
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    ser::{SerializeMap, SerializeSeq},
+};
+
+use crate::{
+    component::{Component, ComponentInfo},
+    entity::Entity,
+    world::{World, archetype::FieldId, command::Command, core::Core},
+};
+
+impl World {
+    /// Write every entity with its serializable components as JSON.
+    /// Components are keyed by their stable id & pairs are written as
+    /// `[relationship stable id, target, value]`. Component entities are skipped.
+    pub fn save<W: Write>(&self, writer: W) -> serde_json::Result<()> {
+        self.crust.mantle(|mantle| {
+            let mut serializer = serde_json::Serializer::pretty(writer);
+            Scene { core: &mantle.core }.serialize(&mut serializer)
+        })
+    }
+
+    /// Spawn every entity in a scene written by [`World::save`]. Returns the spawned entities.
+    /// Ids of entities in the scene (including those stored in components) are remapped to the
+    /// spawned entities. Other ids are kept as is. Changes are applied on the next flush.
+    /// Nothing is spawned if the scene can't be loaded.
+    pub fn load<R: Read>(&self, reader: R) -> serde_json::Result<Vec<Entity>> {
+        let scene: serde_json::Value = serde_json::from_reader(reader)?;
+        let components = self.crust.mantle(|mantle| {
+            (mantle.core.component_infos().into_iter())
+                .filter(|info| info.serde.is_some())
+                .map(|info| (info.stable_id, info))
+                .collect()
+        });
+
+        // Reserve an entity for every saved id before any component refers to it
+        let ids = (scene.as_array().into_iter().flatten())
+            .filter_map(|entity| entity.get("id")?.as_u64());
+        let map: HashMap<_, _> = self.crust.mantle(|mantle| {
+            ids.map(|id| (id, mantle.core.create_uninitialized_entity())).collect()
+        });
+        let staged = {
+            let _guard = EntityMap::begin(map.clone());
+            SceneSeed { components: &components }.deserialize(&scene)
+        };
+
+        self.crust.mantle(|mantle| {
+            let staged = match staged {
+                Ok(staged) => staged,
+                Err(err) => {
+                    // Reserved ids exist after the next flush so despawn them
+                    map.values().for_each(|entity| mantle.enqueue(Command::despawn(*entity)));
+                    return Err(err);
+                }
+            };
+            let mut spawned = Vec::new();
+            for (id, components) in staged {
+                let entity = match id.and_then(|id| map.get(&id)) {
+                    Some(entity) => *entity,
+                    // Entities without an id are always new
+                    None => mantle.core.create_uninitialized_entity(),
+                };
+                mantle.enqueue(Command::spawn(entity));
+                for mut value in components {
+                    let (field, info) = (value.field, value.info);
+                    let bytes = value.bytes.take().unwrap();
+                    // SAFETY: Bytes were deserialized with the component's own impl
+                    mantle.enqueue(unsafe { Command::insert_bytes(field, info, bytes, entity) });
+                }
+                spawned.push(entity);
+            }
+            Ok(spawned)
+        })
+    }
+}
+
+/// Maps saved entity ids to reserved entities while loading
+struct EntityMap;
+
+thread_local! {
+    static ENTITY_MAP: RefCell<Option<HashMap<u64, Entity>>> = const { RefCell::new(None) };
+}
+
+struct EntityMapGuard;
+
+impl Drop for EntityMapGuard {
+    fn drop(&mut self) {
+        ENTITY_MAP.with_borrow_mut(|map| *map = None);
+    }
+}
+
+impl EntityMap {
+    fn begin(map: HashMap<u64, Entity>) -> EntityMapGuard {
+        ENTITY_MAP.with_borrow_mut(|current| *current = Some(map));
+        EntityMapGuard
+    }
+
+    /// Returns `None` if no scene is being loaded or the entity isn't in it
+    fn get(raw: u64) -> Option<Entity> {
+        ENTITY_MAP.with_borrow(|map| map.as_ref()?.get(&raw).copied())
+    }
+}
+
+impl Serialize for Entity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.raw().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = u64::deserialize(deserializer)?;
+        Ok(EntityMap::get(raw).unwrap_or(Entity::from_raw(raw)))
+    }
+}
+
+/// Deserialized value of a component or pair. Dropped with the component's drop if never inserted.
+struct ComponentValue {
+    field: FieldId,
+    info: ComponentInfo,
+    bytes: Option<Box<[MaybeUninit<u8>]>>,
+}
+
+impl Drop for ComponentValue {
+    fn drop(&mut self) {
+        if let Some(bytes) = &self.bytes {
+            // SAFETY: Bytes are a value of the component that was never moved into the world
+            unsafe { self.info.drop_unaligned(bytes) };
+        }
+    }
+}
+
+struct Scene<'a> {
+    core: &'a Core,
+}
+
+/// Serializable values of an entity's fields
+#[derive(Default)]
+struct SceneFields<'a> {
+    components: Vec<(u64, &'a dyn erased_serde::Serialize)>,
+    pairs: Vec<(u64, Entity, &'a dyn erased_serde::Serialize)>,
+}
+
+impl<'a> SceneFields<'a> {
+    fn push(
+        &mut self,
+        core: &Core,
+        field: FieldId,
+        info: &ComponentInfo,
+        value: &'a dyn erased_serde::Serialize,
+    ) {
+        if !field.is_pair() {
+            self.components.push((info.stable_id, value));
+        } else if let Some(target) = field.target_index().and_then(|index| core.entity_at(index)) {
+            self.pairs.push((info.stable_id, target, value));
+        }
+    }
+}
+
+impl Serialize for Scene<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        let sparse_sets: Vec<_> = (self.core.sparse_sets.iter())
+            .map(|(field, sparse_set)| (*field, sparse_set.read()))
+            .filter(|(_, sparse_set)| sparse_set.column.info().serde.is_some())
+            .collect();
+        // Keyed by component so tag pairs are found too
+        let tags: HashMap<FieldId, ComponentInfo> = (self.core.component_infos().into_iter())
+            .filter(|info| info.size == 0 && info.serde.is_some())
+            .map(|info| (info.id.into(), info))
+            .collect();
+        let entity_index = &self.core.entity_index;
+        for archetype in self.core.archetypes() {
+            if archetype.signature.contains(ComponentInfo::id().into()) {
+                continue;
+            }
+            let table = &self.core.tables[archetype.table];
+            let columns: Vec<_> = (table.signature.iter().zip(&table.columns))
+                .map(|(field, column)| (*field, column.read()))
+                .filter(|(_, column)| column.info().serde.is_some())
+                .collect();
+            let tags: Vec<_> = (archetype.signature.iter())
+                .filter_map(|field| Some((*field, tags.get(&field.component().into())?)))
+                .collect();
+            for entity in archetype.entities.iter() {
+                let row = entity_index[*entity].table_row;
+                let mut fields = SceneFields::default();
+                for (field, column) in &columns {
+                    let serialize = column.info().serde.unwrap().serialize;
+                    // SAFETY: Bytes are of the column's component type
+                    let value = unsafe { serialize(column.get_chunk(row)) };
+                    fields.push(self.core, *field, column.info(), value);
+                }
+                for (field, info) in &tags {
+                    // SAFETY: Tags are zero sized so any aligned pointer is valid
+                    let value = unsafe {
+                        let bytes =
+                            std::slice::from_raw_parts(std::ptr::without_provenance(info.align), 0);
+                        (info.serde.unwrap().serialize)(bytes)
+                    };
+                    fields.push(self.core, *field, info, value);
+                }
+                for (field, sparse_set) in &sparse_sets {
+                    let serialize = sparse_set.column.info().serde.unwrap().serialize;
+                    let Some(row) = sparse_set.row(*entity) else {
+                        continue;
+                    };
+                    // SAFETY: Bytes are of the sparse set's component type
+                    let value = unsafe { serialize(sparse_set.column.get_chunk(row)) };
+                    fields.push(self.core, *field, sparse_set.column.info(), value);
+                }
+                seq.serialize_element(&SceneEntity { id: *entity, fields })?;
+            }
+        }
+        seq.end()
+    }
+}
+
+struct SceneEntity<'a> {
+    id: Entity,
+    fields: SceneFields<'a>,
+}
+
+impl Serialize for SceneEntity<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let pairs = !self.fields.pairs.is_empty();
+        let mut map = serializer.serialize_map(Some(2 + pairs as usize))?;
+        map.serialize_entry("id", &self.id)?;
+        map.serialize_entry("components", &SceneComponents(&self.fields.components))?;
+        if pairs {
+            map.serialize_entry("pairs", &self.fields.pairs)?;
+        }
+        map.end()
+    }
+}
+
+struct SceneComponents<'a, 'b>(&'b [(u64, &'a dyn erased_serde::Serialize)]);
+
+impl Serialize for SceneComponents<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().copied())
+    }
+}
+
+/// Saved id & components (including pairs) of an entity
+type StagedEntity = (Option<u64>, Vec<ComponentValue>);
+
+struct SceneSeed<'a> {
+    components: &'a HashMap<u64, ComponentInfo>,
+}
+
+impl<'de> DeserializeSeed<'de> for SceneSeed<'_> {
+    type Value = Vec<StagedEntity>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for SceneSeed<'_> {
+    type Value = Vec<StagedEntity>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of entities")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut entities = Vec::new();
+        while let Some(entity) = seq.next_element_seed(EntitySeed(self.components))? {
+            entities.push(entity);
+        }
+        Ok(entities)
+    }
+}
+
+struct EntitySeed<'a>(&'a HashMap<u64, ComponentInfo>);
+
+impl<'de> DeserializeSeed<'de> for EntitySeed<'_> {
+    type Value = StagedEntity;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntitySeed<'_> {
+    type Value = StagedEntity;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an entity")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut id = None;
+        let mut components = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "id" => id = Some(map.next_value::<u64>()?),
+                "components" => components.extend(map.next_value_seed(ComponentsSeed(self.0))?),
+                "pairs" => components.extend(map.next_value_seed(PairsSeed(self.0))?),
+                other => {
+                    return Err(de::Error::unknown_field(
+                        other,
+                        &["id", "components", "pairs"],
+                    ));
+                }
+            }
+        }
+        Ok((id, components))
+    }
+}
+
+struct ComponentsSeed<'a>(&'a HashMap<u64, ComponentInfo>);
+
+impl<'de> DeserializeSeed<'de> for ComponentsSeed<'_> {
+    type Value = Vec<ComponentValue>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ComponentsSeed<'_> {
+    type Value = Vec<ComponentValue>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of component stable ids to values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut components = Vec::new();
+        while let Some(stable_id) = map.next_key::<u64>()? {
+            let info = lookup::<A::Error>(self.0, stable_id)?;
+            let bytes = map.next_value_seed(ComponentSeed(info))?;
+            components.push(ComponentValue { field: info.id.into(), info, bytes: Some(bytes) });
+        }
+        Ok(components)
+    }
+}
+
+fn lookup<E: de::Error>(
+    components: &HashMap<u64, ComponentInfo>,
+    stable_id: u64,
+) -> Result<ComponentInfo, E> {
+    (components.get(&stable_id).copied())
+        .ok_or_else(|| E::custom(format!("unknown component `{stable_id}`")))
+}
+
+struct PairsSeed<'a>(&'a HashMap<u64, ComponentInfo>);
+
+impl<'de> DeserializeSeed<'de> for PairsSeed<'_> {
+    type Value = Vec<ComponentValue>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for PairsSeed<'_> {
+    type Value = Vec<ComponentValue>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of pairs")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut pairs = Vec::new();
+        while let Some(pair) = seq.next_element_seed(PairSeed(self.0))? {
+            pairs.push(pair);
+        }
+        Ok(pairs)
+    }
+}
+
+struct PairSeed<'a>(&'a HashMap<u64, ComponentInfo>);
+
+impl<'de> DeserializeSeed<'de> for PairSeed<'_> {
+    type Value = ComponentValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(3, self)
+    }
+}
+
+impl<'de> Visitor<'de> for PairSeed<'_> {
+    type Value = ComponentValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a relationship stable id, target & value")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let stable_id = seq.next_element::<u64>()?.ok_or(de::Error::invalid_length(0, &self))?;
+        let info = lookup::<A::Error>(self.0, stable_id)?;
+        // Remapped if the target is in the scene
+        let target = seq.next_element::<Entity>()?.ok_or(de::Error::invalid_length(1, &self))?;
+        let bytes = seq
+            .next_element_seed(ComponentSeed(info))?
+            .ok_or(de::Error::invalid_length(2, &self))?;
+        let field = FieldId::pair(info.id, target);
+        Ok(ComponentValue { field, info, bytes: Some(bytes) })
+    }
+}
+
+struct ComponentSeed(ComponentInfo);
+
+impl<'de> DeserializeSeed<'de> for ComponentSeed {
+    type Value = Box<[MaybeUninit<u8>]>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+        (self.0.serde.unwrap().deserialize)(&mut erased).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as ssecs;
+    use crate::component::tests::*;
+    use ssecs_macros::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Component, Serialize, Deserialize)]
+    struct Position(f32, f32);
+
+    #[derive(Component, Serialize, Deserialize)]
+    struct Target(Entity);
+
+    #[derive(Component, Serialize, Deserialize)]
+    struct Marker;
+
+    #[derive(Component, Serialize, Deserialize)]
+    #[component(storage = "sparse")]
+    struct Burning(u8);
+
+    #[test]
+    fn save_load() {
+        let world = World::new();
+        let a = world.spawn().insert(Position(1.0, 2.0)).insert(Marker).insert(Burning(4)).id();
+        world.spawn().insert(Target(a)).insert(Player);
+        world.flush();
+
+        let mut scene = Vec::new();
+        world.save(&mut scene).unwrap();
+
+        let other = World::new();
+        other.spawn();
+        let loaded = other.load(scene.as_slice()).unwrap();
+        other.flush();
+
+        assert_eq!(2, loaded.len());
+
+        let (a, b) = if other.entity(loaded[0]).has(Position::id()) {
+            (other.entity(loaded[0]), other.entity(loaded[1]))
+        } else {
+            (other.entity(loaded[1]), other.entity(loaded[0]))
+        };
+        let position = a.get::<Position>().unwrap();
+        assert_eq!((1.0, 2.0), (position.0, position.1));
+        assert!(a.has(Marker::id()));
+        assert_eq!(4, a.get::<Burning>().unwrap().0);
+        assert_eq!(a.id(), b.get::<Target>().unwrap().0);
+        // Not serializable
+        assert!(!b.has(Player::id()));
+    }
+
+    #[derive(Component, Serialize, Deserialize)]
+    struct Likes(u8);
+
+    #[test]
+    fn pairs() {
+        let world = World::new();
+        let parent = world.spawn().id();
+        world.spawn().child_of(parent).insert_pair(Likes(3), parent);
+        world.flush();
+
+        let mut scene = Vec::new();
+        world.save(&mut scene).unwrap();
+
+        let other = World::new();
+        other.spawn();
+        let loaded = other.load(scene.as_slice()).unwrap();
+        other.flush();
+
+        let (parent, child) = match other.entity(loaded[0]).parent() {
+            Some(_) => (other.entity(loaded[1]), other.entity(loaded[0])),
+            None => (other.entity(loaded[0]), other.entity(loaded[1])),
+        };
+        assert_eq!(Some(parent.id()), child.parent());
+        assert_eq!(vec![child.id()], parent.children());
+        let likes = other.crust.mantle(|mantle| {
+            let core = &mantle.core;
+            let location = core.entity_location(child.id()).unwrap();
+            let field = FieldId::pair(Likes::id(), parent.id());
+            // SAFETY: Bytes are of a Likes
+            core.get_bytes(field, child.id(), location)
+                .map(|bytes| unsafe { bytes[0].assume_init() })
+        });
+        assert_eq!(Some(3), likes);
+    }
+
+    #[test]
+    fn unknown_component() {
+        let world = World::new();
+        let scene = r#"[{ "components": { "1": null } }]"#;
+        assert!(world.load(scene.as_bytes()).is_err());
+    }
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Component, Serialize, Deserialize)]
+    struct Counted(u8);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn outside_references() {
+        let world = World::new();
+        let global = world.spawn().id();
+        world.flush();
+
+        let target = Target::info().stable_id;
+        let scene = format!(
+            r#"[
+                {{ "id": 1000, "components": {{ "{target}": {} }} }},
+                {{ "id": 1001, "components": {{ "{target}": {} }} }},
+                {{ "components": {{ "{target}": 1000 }} }}
+            ]"#,
+            global.raw(),
+            Position::id().raw(),
+        );
+        let loaded = world.load(scene.as_bytes()).unwrap();
+        world.flush();
+        let target = |n: usize| world.entity(loaded[n]).get::<Target>().unwrap().0;
+        assert_eq!(global, target(0));
+        assert_eq!(Position::id(), target(1));
+        assert_eq!(loaded[0], target(2));
+    }
+
+    #[test]
+    fn rollback() {
+        let world = World::new();
+        let scene = format!(
+            r#"[{{ "id": 100, "components": {{ "{}": 1 }} }}, {{ "components": {{ "1": null }} }}]"#,
+            Counted::info().stable_id
+        );
+        assert!(world.load(scene.as_bytes()).is_err());
+        world.flush();
+        assert_eq!(1, DROPPED.load(Ordering::Relaxed));
+        let counted = world.crust.mantle(|mantle| {
+            (mantle.core.archetypes())
+                .filter(|archetype| archetype.signature.contains(Counted::id().into()))
+                .map(|archetype| archetype.entities.len())
+                .sum::<usize>()
+        });
+        assert_eq!(0, counted);
+    }
+}
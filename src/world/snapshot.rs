@@ -0,0 +1,627 @@
+use std::{collections::HashSet, mem::MaybeUninit};
+
+use crate::{
+    Error,
+    component::{ComponentInfo, Storage},
+    entity::Entity,
+    slotmap::{Key, Slot},
+    world::{
+        World,
+        archetype::{FieldId, RowIndex, Signature},
+        core::{Core, EntityLocation},
+        removal::Removal,
+        sparse::SparseSet,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"SSNP";
+const VERSION: u32 = 4;
+
+/// Copy of every entity whose components are all plain-old-data (`#[component(pod)]`) or tags.
+/// Components are stored by stable id. See [`World::snapshot`].
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    generations: Vec<u32>,
+    available: Vec<usize>,
+    archetypes: Vec<ArchetypeSnapshot>,
+    sparse_sets: Vec<SparseSetSnapshot>,
+}
+
+/// Stable id of a component, with the target index if it's a pair
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FieldKey {
+    component: u64,
+    target: Option<u32>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct ArchetypeSnapshot {
+    fields: Vec<FieldKey>,
+    entities: Vec<Entity>,
+    /// Rows of each field in `fields` order. Empty for tags.
+    columns: Vec<Box<[MaybeUninit<u8>]>>,
+}
+
+/// Rows of captured entities in a sparse set
+#[derive(Clone, Debug)]
+struct SparseSetSnapshot {
+    field: FieldKey,
+    entities: Vec<Entity>,
+    bytes: Box<[MaybeUninit<u8>]>,
+}
+
+/// Fields of a snapshot resolved in the world it's restored into
+struct Resolved {
+    archetypes: Vec<Vec<(FieldId, ComponentInfo)>>,
+    sparse_sets: Vec<(FieldId, ComponentInfo)>,
+    captured: HashSet<Entity>,
+}
+
+impl World {
+    /// Flush & copy the rows of every archetype that only has plain-old-data components.
+    /// Entities with any other component are not captured.
+    pub fn snapshot(&self) -> Snapshot {
+        self.crust.flush_with(|core| core.snapshot())
+    }
+
+    /// Flush & return every captured entity to its state in the snapshot.
+    /// Entities that would be captured by a snapshot but aren't in this one are despawned.
+    /// Other entities are left as is.
+    /// Sparse components that aren't plain-old-data are removed from captured entities.
+    ///
+    /// Nothing is modified if the snapshot can't be restored, e.g. a component is no longer
+    /// registered or plain-old-data, or a captured entity's id is now used by an entity that
+    /// can't be captured.
+    pub fn restore(&self, snapshot: &Snapshot) -> Result<(), Error> {
+        self.crust.flush_with(|core| core.restore(snapshot))
+    }
+}
+
+impl Core {
+    fn field_key(&self, field: FieldId) -> FieldKey {
+        let info = self.component_info(field.component()).unwrap();
+        FieldKey { component: info.stable_id, target: field.target_index() }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let entity_index = &self.entity_index;
+        let pod_archetypes: HashSet<_> = self
+            .archetypes
+            .iter()
+            .filter(|(id, _)| self.archetype_is_pod(*id))
+            .map(|(id, _)| id)
+            .collect();
+        let sparse_sets = self
+            .sparse_sets
+            .iter()
+            .map(|(field, sparse_set)| (field, sparse_set.read()))
+            .filter(|(_, sparse_set)| {
+                let info = sparse_set.column.info();
+                info.pod || info.size == 0
+            })
+            .map(|(field, sparse_set)| {
+                let entities: Vec<_> = sparse_set
+                    .entities
+                    .iter()
+                    .copied()
+                    .filter(|entity| {
+                        let location = entity_index.get(*entity);
+                        location
+                            .is_some_and(|location| pod_archetypes.contains(&location.archetype))
+                    })
+                    .collect();
+                let bytes = entities
+                    .iter()
+                    .flat_map(|entity| {
+                        sparse_set.column.get_chunk(sparse_set.row(*entity).unwrap())
+                    })
+                    .copied()
+                    .collect();
+                SparseSetSnapshot { field: self.field_key(*field), entities, bytes }
+            })
+            .collect();
+        let archetypes = self
+            .archetypes
+            .iter()
+            .filter(|(id, archetype)| pod_archetypes.contains(id) && !archetype.entities.is_empty())
+            .map(|(id, archetype)| ArchetypeSnapshot {
+                fields: archetype.signature.iter().map(|field| self.field_key(*field)).collect(),
+                entities: archetype.entities.clone(),
+                columns: (archetype.signature.iter())
+                    .map(|field| match self.column(*field, id) {
+                        Some(column) => {
+                            let column = column.read();
+                            (archetype.entities.iter())
+                                .flat_map(|entity| {
+                                    column.get_chunk(entity_index[*entity].table_row)
+                                })
+                                .copied()
+                                .collect()
+                        }
+                        None => Box::default(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        Snapshot {
+            generations: entity_index.slots.iter().map(|slot| slot.generation).collect(),
+            available: entity_index.available.clone(),
+            archetypes,
+            sparse_sets,
+        }
+    }
+
+    /// Find a snapshot field in this world. It must be plain-old-data with the same storage.
+    fn resolve_field(
+        &self,
+        key: FieldKey,
+        storage: Storage,
+    ) -> Result<(FieldId, ComponentInfo), Error> {
+        let component = self.stable_index.get(&key.component).copied();
+        let info = (component.and_then(|component| self.component_info(component)))
+            .ok_or(Error::InvalidSnapshot("unknown component"))?;
+        if !info.pod && info.size != 0 {
+            return Err(Error::InvalidSnapshot("component isn't plain-old-data"));
+        }
+        if info.storage != storage {
+            return Err(Error::InvalidSnapshot("component storage changed"));
+        }
+        let field = match key.target {
+            Some(target) => FieldId::pair(info.id, Entity::from_raw(target as u64)),
+            None => info.id.into(),
+        };
+        Ok((field, info))
+    }
+
+    /// Check that a snapshot can be restored without modifying anything
+    fn resolve_snapshot(&self, snapshot: &Snapshot) -> Result<Resolved, Error> {
+        let invalid = Error::InvalidSnapshot;
+        let mut captured = HashSet::new();
+        let mut archetypes = Vec::new();
+        for archetype in &snapshot.archetypes {
+            let fields = (archetype.fields.iter())
+                .map(|key| self.resolve_field(*key, Storage::Table))
+                .collect::<Result<Vec<_>, _>>()?;
+            let ids: Vec<_> = fields.iter().map(|(field, _)| *field).collect();
+            if Signature::new(&ids).iter().count() != fields.len() {
+                return Err(invalid("duplicate field"));
+            }
+            if archetype.columns.len() != fields.len() {
+                return Err(invalid("column count doesn't match fields"));
+            }
+            for ((_, info), column) in fields.iter().zip(&archetype.columns) {
+                if Some(column.len()) != archetype.entities.len().checked_mul(info.size) {
+                    return Err(invalid("column length doesn't match entity count"));
+                }
+            }
+            for entity in archetype.entities.iter().copied() {
+                let Key { index, generation } = Key::from(entity);
+                if generation == 0 || snapshot.generations.get(index as usize) != Some(&generation)
+                {
+                    return Err(invalid("entity doesn't match generations"));
+                }
+                if !captured.insert(entity) {
+                    return Err(invalid("entity captured more than once"));
+                }
+                let slots = &self.entity_index.slots;
+                if let Some(location) = slots.get(index as usize).and_then(|slot| slot.data)
+                    && !self.archetype_is_pod(location.archetype)
+                {
+                    return Err(invalid(
+                        "entity collides with an entity that can't be captured",
+                    ));
+                }
+            }
+            archetypes.push(fields);
+        }
+
+        let mut sparse_sets = Vec::new();
+        for sparse_set in &snapshot.sparse_sets {
+            let (field, info) = self.resolve_field(sparse_set.field, Storage::Sparse)?;
+            if Some(sparse_set.bytes.len()) != sparse_set.entities.len().checked_mul(info.size) {
+                return Err(invalid("sparse set length doesn't match entity count"));
+            }
+            let mut entities = HashSet::new();
+            if !sparse_set
+                .entities
+                .iter()
+                .all(|entity| captured.contains(entity) && entities.insert(*entity))
+            {
+                return Err(invalid("sparse set entity isn't captured exactly once"));
+            }
+            if sparse_sets.iter().any(|(other, _)| *other == field) {
+                return Err(invalid("duplicate field"));
+            }
+            sparse_sets.push((field, info));
+        }
+
+        let mut available = HashSet::new();
+        if !snapshot
+            .available
+            .iter()
+            .all(|n| *n < snapshot.generations.len() && available.insert(*n))
+        {
+            return Err(invalid("free slots aren't distinct"));
+        }
+        Ok(Resolved { archetypes, sparse_sets, captured })
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        let resolved = self.resolve_snapshot(snapshot)?;
+
+        // Remove all capturable entities. Ones that aren't restored count as despawned.
+        let entity_index = &mut self.entity_index;
+        let mut removals = self.removals.lock();
+        let mut despawned = Vec::new();
+        for (_, table) in self.tables.iter_mut().filter(|(_, table)| table.is_pod()) {
+            for entity in table.entities.drain(..) {
+                if !resolved.captured.contains(&entity) {
+                    removals.push(Removal::Despawn(entity));
+                    despawned.push(entity);
+                }
+                entity_index.slots[Key::from(entity).index as usize].data = None;
+                for sparse_set in self.sparse_sets.values_mut() {
+                    sparse_set.get_mut().remove(entity);
+                }
+            }
+            for column in table.columns.iter_mut() {
+                column.get_mut().truncate(0);
+            }
+        }
+        drop(removals);
+        for (_, archetype) in self.archetypes.iter_mut() {
+            if self.tables[archetype.table].entities.is_empty() {
+                archetype.entities.clear();
+                archetype.version += 1;
+            }
+        }
+
+        // Like despawn, pairs targeting removed entities are removed from entities that can't be
+        // captured. Pod tables are empty so entities moved by this aren't removed too.
+        for entity in despawned {
+            self.remove_pairs_targeting(entity)?;
+        }
+
+        // Free slots are reused after every generation either world handed out so handles of
+        // removed entities stay dead. Slots created after the snapshot are kept free.
+        let slots = &mut self.entity_index.slots;
+        for (n, generation) in snapshot.generations.iter().copied().enumerate() {
+            if n == slots.len() {
+                slots.push(Slot::default());
+            }
+            if slots[n].data.is_none() {
+                slots[n].generation = slots[n].generation.max(generation);
+            }
+        }
+
+        // Restored rows count as added
+        let tick = self.tick();
+
+        // Copy rows back into archetypes
+        for (archetype_snapshot, fields) in snapshot.archetypes.iter().zip(&resolved.archetypes) {
+            let ids: Vec<_> = fields.iter().map(|(field, _)| *field).collect();
+            let id = self.create_archetype(Signature::new(&ids));
+            let archetype = &mut self.archetypes[id];
+            let table = &mut self.tables[archetype.table];
+            for (field, bytes) in ids.iter().zip(&archetype_snapshot.columns) {
+                if let Some(column) = table.signature.position(*field) {
+                    // SAFETY: Length was checked to be whole rows of the pod component
+                    unsafe { table.columns[column].get_mut().extend_from_bytes(bytes, tick) };
+                }
+            }
+            for entity in archetype_snapshot.entities.iter().copied() {
+                let slot = &mut self.entity_index.slots[Key::from(entity).index as usize];
+                slot.generation = Key::from(entity).generation;
+                slot.data = Some(EntityLocation {
+                    archetype: id,
+                    row: RowIndex(archetype.entities.len()),
+                    table_row: RowIndex(table.entities.len()),
+                });
+                archetype.push(entity);
+                table.entities.push(entity);
+            }
+        }
+
+        // Copy rows back into sparse sets
+        for (sparse_snapshot, (field, info)) in
+            snapshot.sparse_sets.iter().zip(resolved.sparse_sets)
+        {
+            let growth = self.growth;
+            let sparse_set = (self.sparse_sets.entry(field))
+                .or_insert_with(|| SparseSet::new(info, growth).into())
+                .get_mut();
+            for (n, entity) in sparse_snapshot.entities.iter().enumerate() {
+                let bytes = &sparse_snapshot.bytes[n * info.size..][..info.size];
+                // SAFETY: Length was checked to be whole rows of the pod component
+                unsafe { sparse_set.insert(*entity, bytes, tick) };
+            }
+        }
+
+        // Reuse free slots in the same order as when the snapshot was taken
+        let entity_index = &mut self.entity_index;
+        let slots = &entity_index.slots;
+        let available: Vec<_> = snapshot
+            .available
+            .iter()
+            .copied()
+            .filter(|n| slots.get(*n).is_some_and(|slot| slot.data.is_none()))
+            .collect();
+        let listed: HashSet<_> = available.iter().copied().collect();
+        let mut unlisted: Vec<_> =
+            (0..slots.len()).filter(|n| slots[*n].data.is_none() && !listed.contains(n)).collect();
+        unlisted.extend(available);
+        entity_index.available = unlisted;
+        Ok(())
+    }
+}
+
+impl Snapshot {
+    /// Encode as a compact binary format. Components are stored by stable id.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::from(*MAGIC);
+        out.extend(VERSION.to_le_bytes());
+        write_len(&mut out, self.generations.len());
+        self.generations.iter().for_each(|generation| out.extend(generation.to_le_bytes()));
+        write_len(&mut out, self.available.len());
+        self.available.iter().for_each(|n| out.extend((*n as u32).to_le_bytes()));
+        write_len(&mut out, self.archetypes.len());
+        for archetype in &self.archetypes {
+            write_len(&mut out, archetype.fields.len());
+            archetype.fields.iter().for_each(|field| write_field(&mut out, *field));
+            write_len(&mut out, archetype.entities.len());
+            archetype.entities.iter().for_each(|entity| out.extend(entity.raw().to_le_bytes()));
+            write_len(&mut out, archetype.columns.len());
+            for column in &archetype.columns {
+                out.extend((column.len() as u64).to_le_bytes());
+                // SAFETY: Pod components are checked to not have padding
+                out.extend(column.iter().map(|byte| unsafe { byte.assume_init() }));
+            }
+        }
+        write_len(&mut out, self.sparse_sets.len());
+        for sparse_set in &self.sparse_sets {
+            write_field(&mut out, sparse_set.field);
+            write_len(&mut out, sparse_set.entities.len());
+            sparse_set.entities.iter().for_each(|entity| out.extend(entity.raw().to_le_bytes()));
+            out.extend((sparse_set.bytes.len() as u64).to_le_bytes());
+            // SAFETY: Pod components are checked to not have padding
+            out.extend(sparse_set.bytes.iter().map(|byte| unsafe { byte.assume_init() }));
+        }
+        out
+    }
+
+    /// Decode a snapshot encoded with [`Snapshot::to_bytes`]. Returns `None` if malformed.
+    ///
+    /// # Safety
+    /// Rows must be valid values of the components with the same stable ids in this build,
+    /// e.g. encoded by a build where those components have the same layout.
+    /// [`World::restore`] only checks row lengths.
+    pub unsafe fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+        if reader.take(4)? != MAGIC || reader.u32()? != VERSION {
+            return None;
+        }
+        let generations = (0..reader.u32()?).map(|_| reader.u32()).collect::<Option<_>>()?;
+        let available =
+            (0..reader.u32()?).map(|_| Some(reader.u32()? as usize)).collect::<Option<_>>()?;
+        let archetypes = (0..reader.u32()?)
+            .map(|_| {
+                let fields: Vec<_> =
+                    (0..reader.u32()?).map(|_| reader.field()).collect::<Option<_>>()?;
+                let entities = (0..reader.u32()?)
+                    .map(|_| Some(Entity::from_raw(reader.u64()?)))
+                    .collect::<Option<_>>()?;
+                let columns = (0..reader.u32()?)
+                    .map(|_| {
+                        let len = reader.u64()? as usize;
+                        Some(reader.take(len)?.iter().copied().map(MaybeUninit::new).collect())
+                    })
+                    .collect::<Option<_>>()?;
+                Some(ArchetypeSnapshot { fields, entities, columns })
+            })
+            .collect::<Option<_>>()?;
+        let sparse_sets = (0..reader.u32()?)
+            .map(|_| {
+                let field = reader.field()?;
+                let entities = (0..reader.u32()?)
+                    .map(|_| Some(Entity::from_raw(reader.u64()?)))
+                    .collect::<Option<_>>()?;
+                let len = reader.u64()? as usize;
+                let bytes = reader.take(len)?.iter().copied().map(MaybeUninit::new).collect();
+                Some(SparseSetSnapshot { field, entities, bytes })
+            })
+            .collect::<Option<_>>()?;
+        reader.0.is_empty().then_some(Self { generations, available, archetypes, sparse_sets })
+    }
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    out.extend((len as u32).to_le_bytes());
+}
+
+fn write_field(out: &mut Vec<u8>, field: FieldKey) {
+    out.extend(field.component.to_le_bytes());
+    out.extend(field.target.map_or(u64::MAX, u64::from).to_le_bytes());
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (taken, rest) = self.0.split_at_checked(len)?;
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn field(&mut self) -> Option<FieldKey> {
+        let component = self.u64()?;
+        let target = match self.u64()? {
+            u64::MAX => None,
+            target => Some(u32::try_from(target).ok()?),
+        };
+        Some(FieldKey { component, target })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as ssecs;
+    use crate::component::{Component, tests::*};
+    use crate::hierarchy::ChildOf;
+    use ssecs_macros::*;
+    use std::sync::Arc;
+
+    #[derive(Component, Clone, Copy)]
+    #[component(pod)]
+    struct Position(u32, u32);
+
+    #[derive(Component, Clone, Copy)]
+    #[component(pod, storage = "sparse")]
+    struct Stunned(u8);
+
+    #[derive(Component)]
+    struct Shared(#[allow(dead_code)] Arc<u8>);
+
+    #[test]
+    fn restore() {
+        let world = World::new();
+        let a = world.spawn().insert(Position(0, 0)).insert(Player).id();
+        let b = world.spawn().insert(Position(1, 1)).insert(Stunned(3)).id();
+        let shared = world.spawn().insert(Shared(Arc::new(0))).id();
+        let snapshot = world.snapshot();
+
+        world.entity(a).get_mut::<Position>().unwrap().0 = 5;
+        world.entity(b).despawn();
+        let c = world.spawn().insert(Position(2, 2)).id();
+        world.flush();
+
+        let mut removals = world.removal_reader();
+        world.restore(&snapshot).unwrap();
+        assert_eq!(vec![Removal::Despawn(c)], removals.read());
+        assert_eq!(0, world.entity(a).get::<Position>().unwrap().0);
+        assert!(world.entity(a).has(Player::id()));
+        assert_eq!(1, world.entity(b).get::<Position>().unwrap().1);
+        assert_eq!(3, world.entity(b).get::<Stunned>().unwrap().0);
+        assert!(world.get_entity(c).is_none());
+        assert!(world.entity(shared).has(Shared::id()));
+
+        // Slots are reused in the same order as before the restore but removed entities stay dead
+        let d = world.spawn().id();
+        assert_eq!(Key::from(c).index, Key::from(d).index);
+        assert_ne!(c, d);
+        world.flush();
+        assert!(world.get_entity(c).is_none());
+    }
+
+    #[test]
+    fn generations() {
+        let world = World::new();
+        world.spawn().insert(Position(0, 0)).despawn();
+        world.flush();
+        let snapshot = world.snapshot();
+        let a = world.spawn().insert(Position(1, 1)).id();
+        world.flush();
+
+        world.restore(&snapshot).unwrap();
+        let b = world.spawn().insert(Position(2, 2)).id();
+        world.flush();
+        assert_eq!(Key::from(a).index, Key::from(b).index);
+        assert_ne!(a, b);
+        assert!(world.get_entity(a).is_none());
+    }
+
+    #[test]
+    fn removed_targets() {
+        let world = World::new();
+        let snapshot = world.snapshot();
+        let target = world.spawn().insert(Position(0, 0)).id();
+        let child = world.spawn().insert(Shared(Arc::new(0))).child_of(target).id();
+        world.flush();
+
+        world.restore(&snapshot).unwrap();
+        assert!(world.get_entity(target).is_none());
+        assert_eq!(None, world.entity(child).parent());
+        assert!(!world.entity(child).has(FieldId::pair(ChildOf::id(), target)));
+    }
+
+    #[test]
+    fn bytes() {
+        let world = World::new();
+        let a = world.spawn().insert(Position(3, 4)).id();
+        let bytes = world.snapshot().to_bytes();
+        world.entity(a).despawn();
+        world.flush();
+
+        // SAFETY: Encoded by this build
+        let decode = |bytes: &[u8]| unsafe { Snapshot::from_bytes(bytes) };
+        world.restore(&decode(&bytes).unwrap()).unwrap();
+        assert_eq!(4, world.entity(a).get::<Position>().unwrap().1);
+        assert!(decode(&bytes[1..]).is_none());
+        assert!(decode(&bytes[..bytes.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn invalid() {
+        let world = World::new();
+        let a = world.spawn().insert(Position(3, 4)).insert(Player).insert(Stunned(1)).id();
+        let snapshot = world.snapshot();
+        world.entity(a).get_mut::<Position>().unwrap().0 = 5;
+
+        fn position(snapshot: &mut Snapshot) -> &mut ArchetypeSnapshot {
+            let key = Position::info().stable_id;
+            (snapshot.archetypes.iter_mut())
+                .find(|archetype| archetype.fields.iter().any(|field| field.component == key))
+                .unwrap()
+        }
+        fn column(snapshot: &mut Snapshot) -> usize {
+            let key = Position::info().stable_id;
+            position(snapshot).fields.iter().position(|other| other.component == key).unwrap()
+        }
+        let check = |edit: &dyn Fn(&mut Snapshot)| {
+            let mut snapshot = snapshot.clone();
+            edit(&mut snapshot);
+            assert!(matches!(
+                world.restore(&snapshot),
+                Err(Error::InvalidSnapshot(_))
+            ));
+            assert_eq!(5, world.entity(a).get::<Position>().unwrap().0);
+        };
+        check(&|snapshot| {
+            let n = column(snapshot);
+            let column = &mut position(snapshot).columns[n];
+            *column = column[1..].into();
+        });
+        check(&|snapshot| {
+            let n = column(snapshot);
+            position(snapshot).fields[n].component = 0;
+        });
+        check(&|snapshot| {
+            let n = column(snapshot);
+            position(snapshot).fields[n].component = Shared::info().stable_id;
+        });
+        check(&|snapshot| position(snapshot).columns.clear());
+        check(&|snapshot| snapshot.sparse_sets[0].bytes = Box::default());
+        check(&|snapshot| snapshot.generations.clear());
+        check(&|snapshot| {
+            let archetype = position(snapshot);
+            archetype.entities.push(archetype.entities[0]);
+        });
+
+        let shared = world.spawn().insert(Shared(Arc::new(0))).id();
+        world.flush();
+        check(&|snapshot| {
+            let index = Key::from(shared).index as usize;
+            snapshot.generations.resize(index + 1, 1);
+            snapshot.generations[index] = Key::from(shared).generation;
+            position(snapshot).entities[0] = shared;
+        });
+    }
+}
@@ -0,0 +1,78 @@
+use std::mem::MaybeUninit;
+
+use crate::{
+    component::ComponentInfo,
+    entity::Entity,
+    slotmap::Key,
+    world::{
+        archetype::{Column, RowIndex},
+        table::GrowthPolicy,
+    },
+};
+
+/// Storage for a component with [`Storage::Sparse`](crate::component::Storage::Sparse).
+/// Rows are looked up by entity index instead of by archetype.
+#[derive(Debug)]
+pub(crate) struct SparseSet {
+    sparse: Vec<Option<RowIndex>>,
+    pub entities: Vec<Entity>,
+    pub column: Column,
+}
+
+impl SparseSet {
+    pub fn new(info: ComponentInfo, growth: GrowthPolicy) -> Self {
+        Self { sparse: Vec::new(), entities: Vec::new(), column: Column::new(info, growth) }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        self.column.reserve(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.entities.shrink_to_fit();
+        self.column.shrink_to_fit();
+    }
+
+    pub fn row(&self, entity: Entity) -> Option<RowIndex> {
+        let row = (*self.sparse.get(Key::from(entity).index as usize)?)?;
+        (self.entities[*row] == entity).then_some(row)
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.row(entity).is_some()
+    }
+
+    /// # Safety
+    /// Bytes must be a valid value of the set's component
+    pub unsafe fn insert(&mut self, entity: Entity, bytes: &[MaybeUninit<u8>], tick: u64) {
+        let row = match self.row(entity) {
+            Some(row) => row,
+            None => {
+                let index = Key::from(entity).index as usize;
+                if self.sparse.len() <= index {
+                    self.sparse.resize(index + 1, None);
+                }
+                self.sparse[index] = Some(RowIndex(self.entities.len()));
+                self.entities.push(entity);
+                RowIndex(self.entities.len() - 1)
+            }
+        };
+        // SAFETY: Either overwrites the old value or creates the chunk for a new row
+        unsafe { self.column.write_into(row, bytes, tick) };
+    }
+
+    /// Returns `false` if the entity did not have the component
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        let Some(row) = self.row(entity) else {
+            return false;
+        };
+        self.column.swap_drop(row);
+        self.entities.swap_remove(*row);
+        self.sparse[Key::from(entity).index as usize] = None;
+        if let Some(moved) = self.entities.get(*row) {
+            self.sparse[Key::from(*moved).index as usize] = Some(row);
+        }
+        true
+    }
+}
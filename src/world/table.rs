@@ -0,0 +1,86 @@
+use derive_more::From;
+use parking_lot::RwLock;
+
+use crate::{
+    entity::Entity,
+    slotmap::Key,
+    world::archetype::{Column, RowIndex, Signature},
+};
+
+#[derive(Clone, Copy, Debug, From, PartialEq, Eq, Hash)]
+pub struct TableId(pub Key);
+
+impl TableId {
+    pub(crate) fn empty_table() -> TableId {
+        Self(Key { index: 0, generation: 1 })
+    }
+}
+
+impl From<TableId> for Key {
+    fn from(value: TableId) -> Self {
+        value.0
+    }
+}
+
+/// Component values of every archetype with the same data carrying fields.
+/// Zero sized fields (tags) only exist in the archetype signature.
+#[derive(Debug, Default)]
+pub(crate) struct Table {
+    /// Fields with a column, in column order
+    pub signature: Signature,
+    pub entities: Vec<Entity>,
+    pub columns: Vec<RwLock<Column>>,
+}
+
+impl Table {
+    /// If every component in the table is plain-old-data
+    pub(crate) fn is_pod(&self) -> bool {
+        self.columns.iter().all(|column| column.read().info().pod)
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        for column in &mut self.columns {
+            column.get_mut().reserve(additional);
+        }
+    }
+
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.entities.shrink_to_fit();
+        for column in &mut self.columns {
+            column.get_mut().shrink_to_fit();
+        }
+    }
+
+    /// Returns the entity moved into `row`
+    pub(crate) fn swap_drop(&mut self, row: RowIndex) -> Option<Entity> {
+        self.entities.swap_remove(*row);
+        for column in &mut self.columns {
+            column.get_mut().swap_drop(row);
+        }
+        self.entities.get(*row).copied()
+    }
+}
+
+/// How column buffers grow when they run out of room
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GrowthPolicy {
+    /// Capacity is multiplied by this when a column is full
+    pub(crate) factor: f32,
+    /// Rows allocated the first time a column grows
+    pub(crate) min_rows: usize,
+}
+
+impl GrowthPolicy {
+    /// Panics if `factor` isn't greater than 1
+    pub fn new(factor: f32, min_rows: usize) -> Self {
+        assert!(factor > 1.0, "Growth factor must be greater than 1");
+        Self { factor, min_rows }
+    }
+}
+
+impl Default for GrowthPolicy {
+    fn default() -> Self {
+        Self::new(2.0, 8)
+    }
+}